@@ -0,0 +1,224 @@
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
+use rs_concurrency::{
+    dot_product, multiply_with_threads, AtomicMetrics, AtomicStorage, ConcurrentMetrics, Matrix,
+    Vector,
+};
+use std::thread;
+
+const MATRIX_SIZES: [usize; 3] = [8, 32, 64];
+const THREAD_COUNTS: [usize; 4] = [1, 2, 4, 8];
+const DOT_PRODUCT_LENS: [usize; 4] = [16, 256, 4_096, 65_536];
+const WRITER_COUNTS: [usize; 4] = [1, 2, 4, 8];
+const CONTENTION_WRITER_COUNTS: [usize; 6] = [1, 2, 4, 8, 16, 32];
+// 每个写线程 inc 的次数
+const OPS_PER_WRITER: usize = 10_000;
+
+fn square_matrix(n: usize) -> Matrix<i64> {
+    Matrix::new((0..(n * n) as i64).collect::<Vec<_>>(), n, n)
+}
+
+fn bench_multiply(c: &mut Criterion) {
+    let mut group = c.benchmark_group("multiply");
+
+    for n in MATRIX_SIZES {
+        let a = square_matrix(n);
+        let b = square_matrix(n);
+        group.throughput(Throughput::Elements((n * n) as u64));
+
+        for num_threads in THREAD_COUNTS {
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}x{}", n, n), num_threads),
+                &num_threads,
+                |bencher, &num_threads| {
+                    bencher
+                        .iter(|| multiply_with_threads(black_box(&a), black_box(&b), num_threads))
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_dot_product(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dot_product");
+
+    for len in DOT_PRODUCT_LENS {
+        let data = (0..len as i64).collect::<Vec<_>>();
+        group.throughput(Throughput::Elements(len as u64));
+
+        // dot_product 需要 owned 的 Vector，构造的开销放在 setup 里，不计入测量
+        group.bench_with_input(BenchmarkId::from_parameter(len), &data, |bencher, data| {
+            bencher.iter_batched(
+                || (Vector::new(data.clone()), Vector::new(data.clone())),
+                |(a, b)| dot_product(a, b),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+// 所有写线程 inc 同一个 key，测量竞争下的开销
+fn bench_metrics_inc(c: &mut Criterion) {
+    let mut group = c.benchmark_group("metrics_inc");
+
+    for writers in WRITER_COUNTS {
+        group.throughput(Throughput::Elements((writers * OPS_PER_WRITER) as u64));
+
+        let metrics = AtomicMetrics::new(&["req.page.1"]);
+        group.bench_with_input(
+            BenchmarkId::new("AtomicMetrics", writers),
+            &writers,
+            |bencher, &writers| {
+                bencher.iter(|| {
+                    thread::scope(|s| {
+                        for _ in 0..writers {
+                            s.spawn(|| {
+                                for _ in 0..OPS_PER_WRITER {
+                                    metrics.inc("req.page.1").unwrap();
+                                }
+                            });
+                        }
+                    })
+                })
+            },
+        );
+
+        let metrics = ConcurrentMetrics::new();
+        group.bench_with_input(
+            BenchmarkId::new("ConcurrentMetrics", writers),
+            &writers,
+            |bencher, &writers| {
+                bencher.iter(|| {
+                    thread::scope(|s| {
+                        for _ in 0..writers {
+                            s.spawn(|| {
+                                for _ in 0..OPS_PER_WRITER {
+                                    metrics.inc("req.page.1").unwrap();
+                                }
+                            });
+                        }
+                    })
+                })
+            },
+        );
+
+        // 每个线程一个 LocalMetrics，写入先在本地累加，线程结束时 flush 到同一个 ConcurrentMetrics
+        let metrics = ConcurrentMetrics::new();
+        group.bench_with_input(
+            BenchmarkId::new("LocalMetrics", writers),
+            &writers,
+            |bencher, &writers| {
+                bencher.iter(|| {
+                    thread::scope(|s| {
+                        for _ in 0..writers {
+                            let mut local = metrics.local();
+                            s.spawn(move || {
+                                for _ in 0..OPS_PER_WRITER {
+                                    local.inc("req.page.1");
+                                }
+                            });
+                        }
+                    })
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+// 比较 AtomicMetrics 两种存储方式在线程数增加时的扩展性
+// 相邻的两个 key 被不同的线程写，Inline 时它们可能在同一个 cache line 上（false sharing）
+fn bench_metrics_contention(c: &mut Criterion) {
+    let mut group = c.benchmark_group("metrics_contention");
+    let keys = ["req.page.1", "req.page.2"];
+
+    for writers in CONTENTION_WRITER_COUNTS {
+        group.throughput(Throughput::Elements((writers * OPS_PER_WRITER) as u64));
+
+        for (name, storage) in [
+            ("Inline", AtomicStorage::Inline),
+            ("Striped", AtomicStorage::Striped),
+        ] {
+            let metrics = AtomicMetrics::with_storage(&keys, storage);
+            group.bench_with_input(
+                BenchmarkId::new(name, writers),
+                &writers,
+                |bencher, &writers| {
+                    bencher.iter(|| {
+                        thread::scope(|s| {
+                            for idx in 0..writers {
+                                let metrics = &metrics;
+                                let key = keys[idx % keys.len()];
+                                s.spawn(move || {
+                                    for _ in 0..OPS_PER_WRITER {
+                                        metrics.inc(key).unwrap();
+                                    }
+                                });
+                            }
+                        })
+                    })
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+// 比较通过 key 和通过预先取出的句柄 inc 的开销，一个线程，没有竞争
+fn bench_metrics_handle(c: &mut Criterion) {
+    let mut group = c.benchmark_group("metrics_handle");
+    group.throughput(Throughput::Elements(OPS_PER_WRITER as u64));
+
+    let metrics = AtomicMetrics::new(&["req.page.1"]);
+    group.bench_function("AtomicMetrics/key", |bencher| {
+        bencher.iter(|| {
+            for _ in 0..OPS_PER_WRITER {
+                metrics.inc(black_box("req.page.1")).unwrap();
+            }
+        })
+    });
+    let counter = metrics.counter("req.page.1").unwrap();
+    group.bench_function("AtomicMetrics/handle", |bencher| {
+        bencher.iter(|| {
+            for _ in 0..OPS_PER_WRITER {
+                black_box(&counter).inc();
+            }
+        })
+    });
+
+    let metrics = ConcurrentMetrics::new();
+    group.bench_function("ConcurrentMetrics/key", |bencher| {
+        bencher.iter(|| {
+            for _ in 0..OPS_PER_WRITER {
+                metrics.inc(black_box("req.page.1")).unwrap();
+            }
+        })
+    });
+    let counter = metrics.counter("req.page.1");
+    group.bench_function("ConcurrentMetrics/handle", |bencher| {
+        bencher.iter(|| {
+            for _ in 0..OPS_PER_WRITER {
+                black_box(&counter).inc();
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_multiply,
+    bench_dot_product,
+    bench_metrics_inc,
+    bench_metrics_contention,
+    bench_metrics_handle
+);
+criterion_main!(benches);
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use rand::Rng;
-use rs_concurrency::AtomicMetrics;
+use rs_concurrency::{AtomicMetrics, Metrics, Reporter, StdoutSink};
 use std::thread;
 use std::time::Duration;
 
@@ -25,13 +25,16 @@ fn main() -> Result<()> {
         request_worker(metrics.clone())?;
     }
 
+    // 后台线程每 5 秒打印一次当前值，以及每个 key 这段时间的增量和速率
+    let _reporter = Reporter::spawn(metrics, Duration::from_secs(5), StdoutSink);
+
     loop {
-        thread::sleep(Duration::from_secs(5));
-        println!("{}", metrics);
+        thread::park();
     }
 }
 
-fn task_worker(idx: usize, metrics: AtomicMetrics) -> Result<()> {
+// 只依赖 Metrics trait，换成另一种 metrics 实现时不用改这里
+fn task_worker<M: Metrics>(idx: usize, metrics: M) -> Result<()> {
     // 闭包可以有返回值
     thread::spawn(move || {
         loop {
@@ -48,7 +51,7 @@ fn task_worker(idx: usize, metrics: AtomicMetrics) -> Result<()> {
     Ok(())
 }
 
-fn request_worker(metrics: AtomicMetrics) -> Result<()> {
+fn request_worker<M: Metrics>(metrics: M) -> Result<()> {
     thread::spawn(move || {
         loop {
             let mut rng = rand::thread_rng();
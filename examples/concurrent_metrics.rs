@@ -1,6 +1,6 @@
 use anyhow::Result;
 use rand::Rng;
-use rs_concurrency::ConcurrentMetrics;
+use rs_concurrency::{ConcurrentMetrics, Metrics, Reporter, StdoutSink};
 use std::thread;
 use std::time::Duration;
 
@@ -8,7 +8,8 @@ const N: usize = 2;
 const M: usize = 2;
 
 fn main() -> Result<()> {
-    let metrics = ConcurrentMetrics::new();
+    // page 的取值有 255 种，最多保留 100 个 key，其余的合并到 __overflow__
+    let metrics = ConcurrentMetrics::new().with_max_keys(100);
 
     for idx in 0..N {
         task_worker(idx, metrics.clone())?; // Arc::clone(&metrics.data)
@@ -18,13 +19,16 @@ fn main() -> Result<()> {
         request_worker(metrics.clone())?;
     }
 
+    // 后台线程每 5 秒打印一次当前值，以及每个 key 这段时间的增量和速率
+    let _reporter = Reporter::spawn(metrics, Duration::from_secs(5), StdoutSink);
+
     loop {
-        thread::sleep(Duration::from_secs(5));
-        println!("{}", metrics);
+        thread::park();
     }
 }
 
-fn task_worker(idx: usize, metrics: ConcurrentMetrics) -> Result<()> {
+// 只依赖 Metrics trait，换成另一种 metrics 实现时不用改这里
+fn task_worker<M: Metrics>(idx: usize, metrics: M) -> Result<()> {
     // 闭包可以有返回值
     thread::spawn(move || {
         loop {
@@ -41,7 +45,7 @@ fn task_worker(idx: usize, metrics: ConcurrentMetrics) -> Result<()> {
     Ok(())
 }
 
-fn request_worker(metrics: ConcurrentMetrics) -> Result<()> {
+fn request_worker<M: Metrics>(metrics: M) -> Result<()> {
     thread::spawn(move || {
         loop {
             let mut rng = rand::thread_rng();
@@ -0,0 +1,64 @@
+use anyhow::Result;
+use rand::Rng;
+use rs_concurrency::{MetricsRegistry, Report, Reporter};
+use std::thread;
+use std::time::Duration;
+
+const N: usize = 2;
+const M: usize = 2;
+const PAGES: usize = 4;
+
+fn main() -> Result<()> {
+    let registry = MetricsRegistry::new();
+
+    for idx in 0..N {
+        task_worker(idx, registry.clone())?;
+    }
+
+    for _ in 0..M {
+        request_worker(registry.clone())?;
+    }
+
+    // 每 5 秒在后台线程中打印一次，包括每个 counter 的变化量和速率
+    let _reporter = Reporter::spawn(registry.clone(), Duration::from_secs(5), {
+        let registry = registry.clone();
+        move |report: &Report| {
+            println!("{}", report);
+            println!("req total: {}", registry.sum("req"));
+            Ok(())
+        }
+    });
+
+    loop {
+        thread::park();
+    }
+}
+
+fn task_worker(idx: usize, registry: MetricsRegistry) -> Result<()> {
+    // 句柄在循环外获取一次，循环内的 inc 不需要拼接 key，也不需要分配 String
+    let counter = registry.counter_with_labels("call.thread", &[("worker", &idx.to_string())])?;
+
+    thread::spawn(move || loop {
+        let mut rng = rand::thread_rng();
+        thread::sleep(Duration::from_millis(rng.gen_range(100..5_000)));
+        counter.inc();
+    });
+
+    Ok(())
+}
+
+fn request_worker(registry: MetricsRegistry) -> Result<()> {
+    let counters = (1..=PAGES)
+        .map(|page| registry.counter_with_labels("req", &[("page", &page.to_string())]))
+        .collect::<Result<Vec<_>>>()?;
+
+    thread::spawn(move || loop {
+        let mut rng = rand::thread_rng();
+        thread::sleep(Duration::from_millis(rng.gen_range(50..800)));
+
+        let page = rng.gen_range(0..PAGES);
+        counters[page].inc();
+    });
+
+    Ok(())
+}
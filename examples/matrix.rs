@@ -1,6 +1,5 @@
 use anyhow::Result;
 use rs_concurrency::Matrix;
-use std::fmt::Debug;
 
 fn main() -> Result<()> {
     let a = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
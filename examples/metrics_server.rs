@@ -0,0 +1,49 @@
+use anyhow::Result;
+use rand::Rng;
+use rs_concurrency::{MetricsLayer, MetricsRegistry, MetricsServer};
+use std::time::Duration;
+use tracing::instrument;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+const PAGES: usize = 4;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let registry = MetricsRegistry::new();
+    // 日志照常输出，同时 span 的耗时和 event 的数量也记录到 registry 中
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer())
+        .with(MetricsLayer::new(registry.clone()))
+        .init();
+
+    registry.describe("req", "Number of requests per page.");
+
+    // curl http://127.0.0.1:9000/metrics
+    let server = MetricsServer::bind("127.0.0.1:9000", registry.clone()).await?;
+
+    let counters = (1..=PAGES)
+        .map(|page| registry.counter_with_labels("req", &[("page", &page.to_string())]))
+        .collect::<Result<Vec<_>>>()?;
+
+    tokio::spawn(async move {
+        loop {
+            let (sleep, page) = {
+                let mut rng = rand::thread_rng();
+                (rng.gen_range(50..800), rng.gen_range(0..PAGES))
+            };
+            handle_request(sleep).await;
+            counters[page].inc();
+        }
+    });
+
+    // Ctrl-C 之后等正在处理的请求结束再退出
+    tokio::signal::ctrl_c().await?;
+    server.shutdown().await
+}
+
+// 耗时记录在 tracing_span_duration{span="handle_request"} 中
+#[instrument]
+async fn handle_request(millis: u64) {
+    tokio::time::sleep(Duration::from_millis(millis)).await;
+}
@@ -0,0 +1,311 @@
+use super::{par_chunks_mut, par_ranges, Matrix};
+use crate::Vector;
+use anyhow::{anyhow, Result};
+use std::ops::{Add, AddAssign, Div, Mul, Sub};
+
+// 逐元素的运算：数据量超过 PARALLEL_THRESHOLD 时按块分给多个线程，否则顺序执行
+// 每个线程只读自己那一段的数据，结果按段的顺序拼回去，所以输出顺序和顺序执行一致
+impl<T> Matrix<T>
+where
+    T: Copy + Sync,
+{
+    /// 对每个元素执行 f，返回同样形状的新矩阵
+    pub fn map<U, F>(&self, f: F) -> Matrix<U>
+    where
+        U: Send,
+        F: Fn(T) -> U + Sync,
+    {
+        let data = par_ranges(self.data.len(), 1, |range| {
+            self.data[range].iter().map(|&x| f(x)).collect::<Vec<_>>()
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+        Matrix::new(data, self.row, self.col)
+    }
+
+    /// 两个同样形状的矩阵逐元素执行 f
+    pub fn zip_with<U, F>(&self, other: &Matrix<T>, f: F) -> Result<Matrix<U>>
+    where
+        U: Send,
+        F: Fn(T, T) -> U + Sync,
+    {
+        if self.row != other.row || self.col != other.col {
+            return Err(anyhow!("matrix zip error: shape mismatch"));
+        }
+
+        let data = par_ranges(self.data.len(), 1, |range| {
+            self.data[range.clone()]
+                .iter()
+                .zip(&other.data[range])
+                .map(|(&a, &b)| f(a, b))
+                .collect::<Vec<_>>()
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+        Ok(Matrix::new(data, self.row, self.col))
+    }
+
+    /// 最小值，空矩阵返回 None
+    pub fn min(&self) -> Option<T>
+    where
+        T: PartialOrd + Send,
+    {
+        self.reduce(|a, b| if b < a { b } else { a })
+    }
+
+    /// 最大值，空矩阵返回 None
+    pub fn max(&self) -> Option<T>
+    where
+        T: PartialOrd + Send,
+    {
+        self.reduce(|a, b| if b > a { b } else { a })
+    }
+
+    // 每个线程先在自己的段内 reduce，主线程再把各段的结果 reduce 一次
+    fn reduce<F>(&self, f: F) -> Option<T>
+    where
+        T: Send,
+        F: Fn(T, T) -> T + Sync,
+    {
+        par_ranges(self.data.len(), 1, |range| {
+            self.data[range].iter().copied().reduce(&f)
+        })
+        .into_iter()
+        .flatten()
+        .reduce(&f)
+    }
+}
+
+impl<T> Matrix<T>
+where
+    T: Copy + Default + Add<Output = T> + AddAssign + Send + Sync,
+{
+    /// 所有元素的和
+    pub fn sum(&self) -> T {
+        let mut sum = T::default();
+        for partial in par_ranges(self.data.len(), 1, |range| {
+            let mut partial = T::default();
+            for &x in &self.data[range] {
+                partial += x;
+            }
+            partial
+        }) {
+            sum += partial;
+        }
+
+        sum
+    }
+
+    /// 每一行的和，结果长度为 row
+    pub fn row_sums(&self) -> Vector<T> {
+        if self.col == 0 {
+            return Vector::new(vec![T::default(); self.row]);
+        }
+
+        // 按整行切分，一行不会被分到两个线程里
+        let data = par_ranges(self.data.len(), self.col, |range| {
+            self.data[range]
+                .chunks(self.col)
+                .map(|row| {
+                    let mut sum = T::default();
+                    for &x in row {
+                        sum += x;
+                    }
+                    sum
+                })
+                .collect::<Vec<_>>()
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+        Vector::new(data)
+    }
+
+    /// 每一列的和，结果长度为 col
+    pub fn col_sums(&self) -> Vector<T> {
+        let mut data = vec![T::default(); self.col];
+        if self.col == 0 {
+            return Vector::new(data);
+        }
+
+        // 每个线程算出自己那几行的部分和，主线程再累加
+        let partials = par_ranges(self.data.len(), self.col, |range| {
+            let mut partial = vec![T::default(); self.col];
+            for row in self.data[range].chunks(self.col) {
+                for (p, &x) in partial.iter_mut().zip(row) {
+                    *p += x;
+                }
+            }
+            partial
+        });
+
+        for partial in partials {
+            for (value, p) in data.iter_mut().zip(partial) {
+                *value += p;
+            }
+        }
+
+        Vector::new(data)
+    }
+}
+
+impl<T: Send> Matrix<T> {
+    /// 原地修改每个元素，不分配新的 data
+    pub fn apply_inplace<F>(&mut self, f: F)
+    where
+        F: Fn(&mut T) + Sync,
+    {
+        par_chunks_mut(&mut self.data, |chunk| chunk.iter_mut().for_each(&f));
+    }
+}
+
+// 标量广播：矩阵的每个元素都和同一个标量做运算
+impl<T> Add<T> for &Matrix<T>
+where
+    T: Copy + Add<Output = T> + Send + Sync,
+{
+    type Output = Matrix<T>;
+
+    fn add(self, rhs: T) -> Self::Output {
+        self.map(|x| x + rhs)
+    }
+}
+
+impl<T> Sub<T> for &Matrix<T>
+where
+    T: Copy + Sub<Output = T> + Send + Sync,
+{
+    type Output = Matrix<T>;
+
+    fn sub(self, rhs: T) -> Self::Output {
+        self.map(|x| x - rhs)
+    }
+}
+
+impl<T> Mul<T> for &Matrix<T>
+where
+    T: Copy + Mul<Output = T> + Send + Sync,
+{
+    type Output = Matrix<T>;
+
+    fn mul(self, rhs: T) -> Self::Output {
+        self.map(|x| x * rhs)
+    }
+}
+
+impl<T> Div<T> for &Matrix<T>
+where
+    T: Copy + Div<Output = T> + Send + Sync,
+{
+    type Output = Matrix<T>;
+
+    fn div(self, rhs: T) -> Self::Output {
+        self.map(|x| x / rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 超过 PARALLEL_THRESHOLD，走多线程的路径
+    const BIG_ROW: usize = 301;
+    const BIG_COL: usize = 97;
+
+    fn big_matrix() -> Matrix<i64> {
+        Matrix::new(
+            (0..(BIG_ROW * BIG_COL) as i64).collect::<Vec<_>>(),
+            BIG_ROW,
+            BIG_COL,
+        )
+    }
+
+    #[test]
+    fn test_map() {
+        let m = Matrix::new([1, 2, 3, 4], 2, 2);
+        let r = m.map(|x| x * 10);
+        assert_eq!(r.data, vec![10, 20, 30, 40]);
+
+        let m = big_matrix();
+        let r = m.map(|x| x + 1);
+        assert_eq!(r.data, (1..=(BIG_ROW * BIG_COL) as i64).collect::<Vec<_>>());
+        assert_eq!((r.row, r.col), (BIG_ROW, BIG_COL));
+    }
+
+    #[test]
+    fn test_zip_with() -> Result<()> {
+        let a = Matrix::new([1, 2, 3, 4], 2, 2);
+        let b = Matrix::new([4, 3, 2, 1], 2, 2);
+        assert_eq!(a.zip_with(&b, |x, y| x * y)?.data, vec![4, 6, 6, 4]);
+
+        let a = big_matrix();
+        let b = big_matrix();
+        let r = a.zip_with(&b, |x, y| x + y)?;
+        assert_eq!(r.data, a.map(|x| x * 2).data);
+
+        let c = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        assert!(a.zip_with(&c, |x, y| x + y).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sum_min_max() {
+        let m = big_matrix();
+        let n = (BIG_ROW * BIG_COL) as i64;
+        assert_eq!(m.sum(), n * (n - 1) / 2);
+        assert_eq!(m.min(), Some(0));
+        assert_eq!(m.max(), Some(n - 1));
+
+        let empty = Matrix::<i64>::new(vec![], 0, 0);
+        assert_eq!(empty.sum(), 0);
+        assert_eq!(empty.min(), None);
+        assert_eq!(empty.max(), None);
+    }
+
+    #[test]
+    fn test_row_sums_col_sums() {
+        let m = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        assert_eq!(*m.row_sums(), vec![6, 15]);
+        assert_eq!(*m.col_sums(), vec![5, 7, 9]);
+
+        let m = big_matrix();
+        let row_sums = m.row_sums();
+        let col_sums = m.col_sums();
+        assert_eq!(row_sums.len(), BIG_ROW);
+        assert_eq!(col_sums.len(), BIG_COL);
+        for i in 0..BIG_ROW {
+            assert_eq!(
+                row_sums[i],
+                m.data[i * BIG_COL..(i + 1) * BIG_COL].iter().sum::<i64>()
+            );
+        }
+        for j in 0..BIG_COL {
+            assert_eq!(
+                col_sums[j],
+                m.data[j..].iter().step_by(BIG_COL).sum::<i64>()
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply_inplace() {
+        let mut m = big_matrix();
+        m.apply_inplace(|x| *x *= 3);
+        assert_eq!(m.data, big_matrix().map(|x| x * 3).data);
+    }
+
+    #[test]
+    fn test_scalar_ops() {
+        let m = Matrix::new([2, 4, 6, 8], 2, 2);
+        assert_eq!((&m + 1).data, vec![3, 5, 7, 9]);
+        assert_eq!((&m - 1).data, vec![1, 3, 5, 7]);
+        assert_eq!((&m * 2).data, vec![4, 8, 12, 16]);
+        assert_eq!((&m / 2).data, vec![1, 2, 3, 4]);
+    }
+}
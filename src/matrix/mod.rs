@@ -0,0 +1,688 @@
+mod elementwise;
+mod view;
+
+pub use view::MatrixView;
+
+use crate::{dot_product, Vector};
+use anyhow::anyhow;
+use anyhow::Result;
+use std::fmt::{Debug, Display, Formatter};
+use std::ops::{Add, AddAssign, Mul, Range};
+use std::sync::mpsc;
+use std::thread;
+
+const NUM_THREADS: usize = 4;
+
+/// 元素个数少于这个值时直接在当前线程顺序处理，创建线程的开销比计算本身还大
+const PARALLEL_THRESHOLD: usize = 1 << 14;
+
+// [[1, 2], [1, 2], [1, 2]] => [1, 2, 1, 2, 1, 2]
+pub struct Matrix<T> {
+    data: Vec<T>,
+    row: usize,
+    col: usize,
+}
+
+/// 发送给子线程进行点积运算的 消息
+pub struct MsgInput<T> {
+    idx: usize,
+    row: Vector<T>,
+    col: Vector<T>,
+}
+/// 子线程计算完后，将值进行返回的消息
+pub struct MsgOutput<T> {
+    idx: usize,
+    value: T,
+}
+
+/// 发送给子线程的消息：输入数据 + 返回结果用的 oneshot sender
+pub struct Msg<T> {
+    input: MsgInput<T>,
+
+    // sender to send the result back
+    // 这是子线程将结果发回来的 channel sender，主线程接收这个结果
+    // 计算出结果后，可以通过这个 sender 发回来给我
+    sender: oneshot::Sender<MsgOutput<T>>,
+}
+
+/// 为什么这里使用 引用，因为只是做乘法，读数据就可以了
+/// 为什么还要实现 copy 因为在做乘法时，可以直接拿来用，不用再解引用
+/// `T` cannot be sent between threads safely
+pub fn multiply<T>(a: &Matrix<T>, b: &Matrix<T>) -> Result<Matrix<T>>
+where
+    T: Copy + Default + Add<Output = T> + Mul<Output = T> + AddAssign + Send + 'static, // 为能在 线程之间传送
+{
+    multiply_with_threads(a, b, NUM_THREADS)
+}
+
+/// 和 multiply 一样，但是可以指定工作线程的数量，方便 benchmark 比较不同的线程数
+pub fn multiply_with_threads<T>(
+    a: &Matrix<T>,
+    b: &Matrix<T>,
+    num_threads: usize,
+) -> Result<Matrix<T>>
+where
+    T: Copy + Default + Add<Output = T> + Mul<Output = T> + AddAssign + Send + 'static,
+{
+    if a.col != b.row {
+        return Err(anyhow!("matrix multiply error: a.col != b.row"));
+    }
+
+    if num_threads == 0 {
+        return Err(anyhow!("matrix multiply error: num_threads must be > 0"));
+    }
+
+    // let mut data = Vec::with_capacity(a.row * b.col);
+    // 这里不能直接 使用 vec![0; a.row * b.col] 进行初始化，data的类型是 Vec<i32>，而不是 Vec<T>
+    // let mut data = vec![0; a.row * b.col];
+    // let mut data = vec![T::default(); a.row * b.col];
+    // for i in 0..a.row {
+    //     for j in 0..b.col {
+    //         for k in 0..a.col {
+    //             data[i * b.col + j] += a.data[i * a.col + k] * b.data[k * b.col + j];
+    //         }
+    //     }
+    // }
+
+    // 先创建 num_threads 个 thread ，接收消息并进行点积运算
+    // 每个 thread 都需要一个 channel，建立主线程 和 子线程的通信渠道
+    let senders = (0..num_threads)
+        // _ignore 代表是第几个线程，不重要
+        .map(|_ignore| {
+            // sender 由主线程使用，发送消息
+            // receiver 在子线程中，接收消息，并进行点积运算
+            let (sender, receiver) = mpsc::channel::<Msg<T>>();
+
+            thread::spawn(move || {
+                // receiver 收到 message，对 message 的 input 进行点积运算
+                for msg in receiver {
+                    let value = dot_product(msg.input.row, msg.input.col)?;
+
+                    if let Err(e) = msg.sender.send(MsgOutput {
+                        idx: msg.input.idx,
+                        value,
+                    }) {
+                        eprintln!("Send error: {:?}", e);
+                    }
+                }
+
+                Ok::<_, anyhow::Error>(())
+            });
+
+            sender
+        })
+        .collect::<Vec<_>>();
+
+    // generate 4 threads which receive msg and do dot product
+    let matrix_len = a.row * b.col;
+    let mut data = vec![T::default(); matrix_len];
+    let mut receivers = Vec::with_capacity(matrix_len);
+
+    for i in 0..a.row {
+        for j in 0..b.col {
+            // 先拿出 row 和 col 然后进行 点积运算
+            let row = Vector::new(&a.data[i * a.col..(i + 1) * a.col]);
+            let col_data = b.data[j..]
+                .iter()
+                .step_by(b.col)
+                .copied()
+                .collect::<Vec<_>>();
+
+            let col = Vector::new(col_data);
+
+            let idx = i * b.col + j;
+            // 用行和列生成消息
+            let input = MsgInput::new(idx, row, col);
+
+            // 创建了一个 oneshot::Sender（一次性的），每个线程计算完后，可以返回一个 结果 ，通过 receiver.recv 结果
+            let (sender, receiver) = oneshot::channel();
+            let msg = Msg::new(input, sender);
+
+            // 发送的动作非常快，不用管他执行完
+            if let Err(e) = senders[idx % num_threads].send(msg) {
+                eprintln!("Send error: {:?}", e);
+            }
+
+            // 由 receiver 去收集结果
+            receivers.push(receiver);
+        }
+    }
+
+    // map reduce: reduce phase
+    for receiver in receivers {
+        let output = receiver.recv()?;
+        data[output.idx] = output.value;
+    }
+
+    Ok(Matrix {
+        data,
+        row: a.row,
+        col: b.col,
+    })
+}
+
+/// 批量矩阵乘法：每个 (a, b) 对作为一个整体分发给工作线程
+/// 小矩阵如果走 multiply，每次都要创建 4 个线程、为每个元素发一条消息，开销比计算本身还大
+/// 这里整个 pair 在一个线程里顺序计算，线程只创建一次
+/// 输出顺序和输入顺序一致，每个 pair 的错误单独返回，不影响其他 pair
+pub fn multiply_batch<T>(pairs: &[(Matrix<T>, Matrix<T>)]) -> Vec<Result<Matrix<T>>>
+where
+    T: Copy + Default + Add<Output = T> + Mul<Output = T> + AddAssign + Send + Sync,
+{
+    // 只有一个 pair 时没有可以并行的，直接在当前线程计算，不创建线程
+    if pairs.len() <= 1 {
+        return pairs.iter().map(|(a, b)| multiply_seq(a, b)).collect();
+    }
+
+    // 线程数不超过 pair 的数量，小 batch 不会创建用不上的线程
+    let num_threads = NUM_THREADS.min(pairs.len());
+
+    // 借用 pairs，不需要把矩阵 clone 一份再 move 到线程中
+    thread::scope(|s| {
+        let senders = (0..num_threads)
+            .map(|_ignore| {
+                let (sender, receiver) = mpsc::channel::<BatchMsg<'_, T>>();
+
+                s.spawn(move || {
+                    for msg in receiver {
+                        let (a, b) = msg.pair;
+                        let output = BatchOutput {
+                            value: multiply_seq(a, b),
+                        };
+
+                        if let Err(e) = msg.sender.send(output) {
+                            eprintln!("Send error: {:?}", e);
+                        }
+                    }
+                });
+
+                sender
+            })
+            .collect::<Vec<_>>();
+
+        let mut receivers = Vec::with_capacity(pairs.len());
+        for (idx, pair) in pairs.iter().enumerate() {
+            let (sender, receiver) = oneshot::channel();
+            let msg = BatchMsg { pair, sender };
+
+            if let Err(e) = senders[idx % num_threads].send(msg) {
+                eprintln!("Send error: {:?}", e);
+            }
+
+            receivers.push(receiver);
+        }
+
+        // 关闭 channel，工作线程的 for 循环才能结束，scope 才能退出
+        drop(senders);
+
+        // receivers 是按输入顺序 push 的，按顺序 recv 就保证了输出顺序
+        receivers
+            .into_iter()
+            .map(|receiver| match receiver.recv() {
+                Ok(output) => output.value,
+                Err(e) => Err(anyhow!("matrix batch worker error: {}", e)),
+            })
+            .collect()
+    })
+}
+
+/// 单线程的矩阵乘法，batch 中每个 pair 在一个工作线程里用它计算
+fn multiply_seq<T>(a: &Matrix<T>, b: &Matrix<T>) -> Result<Matrix<T>>
+where
+    T: Copy + Default + Add<Output = T> + Mul<Output = T> + AddAssign,
+{
+    if a.col != b.row {
+        return Err(anyhow!("matrix multiply error: a.col != b.row"));
+    }
+
+    let mut data = vec![T::default(); a.row * b.col];
+    for i in 0..a.row {
+        for j in 0..b.col {
+            for k in 0..a.col {
+                data[i * b.col + j] += a.data[i * a.col + k] * b.data[k * b.col + j];
+            }
+        }
+    }
+
+    Ok(Matrix {
+        data,
+        row: a.row,
+        col: b.col,
+    })
+}
+
+/// 把 0..len 按 unit 的整数倍切成最多 NUM_THREADS 段，每段交给一个线程执行 f，结果按段的顺序返回
+/// unit 保证切分不会把一行切断，按行处理时传 col，逐元素处理时传 1
+/// len 小于 PARALLEL_THRESHOLD 时不创建线程，整个 0..len 作为一段
+fn par_ranges<R, F>(len: usize, unit: usize, f: F) -> Vec<R>
+where
+    R: Send,
+    F: Fn(Range<usize>) -> R + Sync,
+{
+    if len < PARALLEL_THRESHOLD || unit == 0 {
+        return vec![f(0..len)];
+    }
+
+    // len 不足一个 unit 时按一个 unit 切，否则 chunk_size 是 0，step_by 会 panic
+    let chunk_size = ((len / unit).div_ceil(NUM_THREADS) * unit).max(unit);
+
+    thread::scope(|s| {
+        let handles = (0..len)
+            .step_by(chunk_size)
+            .map(|start| {
+                let f = &f;
+                s.spawn(move || f(start..(start + chunk_size).min(len)))
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            // f 中的 panic 原样传给调用者
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|e| std::panic::resume_unwind(e))
+            })
+            .collect()
+    })
+}
+
+/// par_ranges 的可变版本，每个线程拿到互不重叠的一段 &mut [T]
+fn par_chunks_mut<T, F>(data: &mut [T], f: F)
+where
+    T: Send,
+    F: Fn(&mut [T]) + Sync,
+{
+    if data.len() < PARALLEL_THRESHOLD {
+        f(data);
+        return;
+    }
+
+    let chunk_size = data.len().div_ceil(NUM_THREADS);
+
+    thread::scope(|s| {
+        for chunk in data.chunks_mut(chunk_size) {
+            let f = &f;
+            s.spawn(move || f(chunk));
+        }
+    });
+}
+
+/// 批量乘法发送给工作线程的消息，pair 是借用的
+struct BatchMsg<'a, T> {
+    pair: &'a (Matrix<T>, Matrix<T>),
+    sender: oneshot::Sender<BatchOutput<T>>,
+}
+
+/// 工作线程计算完一个 pair 后返回的结果
+struct BatchOutput<T> {
+    value: Result<Matrix<T>>,
+}
+
+impl<T> Matrix<T> {
+    // 任何数据结构，只要能够 convert 成 Vec，就可以传入
+    pub fn new(data: impl Into<Vec<T>>, row: usize, col: usize) -> Self {
+        Self {
+            data: data.into(),
+            row,
+            col,
+        }
+    }
+}
+
+impl<T> Matrix<T>
+where
+    T: Copy + Default + Add<Output = T> + Mul<Output = T> + AddAssign + Send + Sync,
+{
+    /// 矩阵乘以向量：m(row x col) * v(col) => row 维的向量
+    /// 不走 multiply 的逐元素 Msg，而是通过 par_ranges 按整行分块，每个线程算一块，小矩阵直接在当前线程计算
+    pub fn mul_vec(&self, v: &Vector<T>) -> Result<Vector<T>> {
+        if self.col != v.len() {
+            return Err(anyhow!("matrix vector multiply error: m.col != v.len"));
+        }
+
+        if self.col == 0 {
+            return Ok(Vector::new(vec![T::default(); self.row]));
+        }
+
+        // 每段都是完整的几行，各自算出这几行的结果，按顺序拼起来就是输出
+        let data = par_ranges(self.data.len(), self.col, |range| {
+            self.data[range]
+                .chunks(self.col)
+                .map(|row| {
+                    let mut sum = T::default();
+                    for k in 0..self.col {
+                        sum += row[k] * v[k];
+                    }
+                    sum
+                })
+                .collect::<Vec<_>>()
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+        Ok(Vector::new(data))
+    }
+}
+
+impl<T> Vector<T>
+where
+    T: Copy + Default + Add<Output = T> + Mul<Output = T> + AddAssign + Send + Sync,
+{
+    /// 向量乘以矩阵：v(row) * m(row x col) => col 维的向量
+    /// 同样通过 par_ranges 按行分块，每个线程算出自己那几行的部分和，最后在主线程中累加
+    pub fn mul_mat(&self, m: &Matrix<T>) -> Result<Vector<T>> {
+        if self.len() != m.row {
+            return Err(anyhow!("vector matrix multiply error: v.len != m.row"));
+        }
+
+        let mut data = vec![T::default(); m.col];
+        if m.col == 0 {
+            return Ok(Vector::new(data));
+        }
+
+        let partials = par_ranges(m.data.len(), m.col, |range| {
+            let mut partial = vec![T::default(); m.col];
+            let coefs = &self[range.start / m.col..range.end / m.col];
+            for (&coef, row) in coefs.iter().zip(m.data[range].chunks(m.col)) {
+                for (p, &x) in partial.iter_mut().zip(row) {
+                    *p += coef * x;
+                }
+            }
+            partial
+        });
+
+        // reduce phase: 把每个线程的部分和加起来
+        for partial in partials {
+            for (value, p) in data.iter_mut().zip(partial) {
+                *value += p;
+            }
+        }
+
+        Ok(Vector::new(data))
+    }
+}
+
+impl<T: Display> Display for Matrix<T> {
+    // display as 2x3 as [1 2 3, 4 5 6], 3x2 as [1 2, 3 4, 5 6]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{")?;
+        for i in 0..self.row {
+            for j in 0..self.col {
+                write!(f, "{}", self.data[i * self.col + j])?;
+                if j != self.col - 1 {
+                    write!(f, " ")?;
+                }
+            }
+
+            if i != self.row - 1 {
+                write!(f, ", ")?;
+            }
+        }
+        write!(f, "}}")?;
+        Ok(())
+    }
+}
+
+impl<T: Display> Debug for Matrix<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Matrix(row={}, col={}, {})", self.row, self.col, self)
+    }
+}
+
+impl<T> MsgInput<T> {
+    pub fn new(idx: usize, row: Vector<T>, col: Vector<T>) -> Self {
+        Self { idx, row, col }
+    }
+}
+
+impl<T> Msg<T> {
+    pub fn new(input: MsgInput<T>, sender: oneshot::Sender<MsgOutput<T>>) -> Self {
+        Self { input, sender }
+    }
+}
+
+impl<T> Mul for Matrix<T>
+where
+    T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + Send + 'static,
+{
+    type Output = Matrix<T>;
+    // type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        multiply(&self, &rhs).expect("Matrix multiply error")
+    }
+}
+
+impl<T> Mul<&Vector<T>> for &Matrix<T>
+where
+    T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + Send + Sync,
+{
+    type Output = Vector<T>;
+
+    fn mul(self, rhs: &Vector<T>) -> Self::Output {
+        self.mul_vec(rhs).expect("Matrix vector multiply error")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::Result;
+
+    #[test]
+    #[allow(unused_variables, clippy::needless_borrows_for_generic_args)]
+    fn test_matrix_new() {
+        let arr = [1, 2, 3, 4, 5, 6];
+        let matrix = Matrix::new(&arr, 2, 3);
+    }
+
+    #[test]
+    fn test_matrix_multiply() -> Result<()> {
+        let a = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        let b = Matrix::new([1, 2, 3, 4, 5, 6], 3, 2);
+
+        // let c = multiply(&a, &b)?;
+        let c = a * b;
+
+        assert_eq!(c.col, 2);
+        assert_eq!(c.row, 2);
+        assert_eq!(c.data, vec![22, 28, 49, 64]);
+
+        assert_eq!(format!("{:?}", c), "Matrix(row=2, col=2, {22 28, 49 64})");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_display() -> Result<()> {
+        let a = Matrix::new([1, 2, 3, 4], 2, 2);
+        let b = Matrix::new([1, 2, 3, 4], 2, 2);
+
+        // let c = multiply(&a, &b)?;
+
+        let c = a * b;
+
+        assert_eq!(c.col, 2);
+        assert_eq!(c.row, 2);
+        assert_eq!(c.data, vec![7, 10, 15, 22]);
+
+        assert_eq!(format!("{:?}", c), "Matrix(row=2, col=2, {7 10, 15 22})");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_a_can_not_multiply_b() {
+        let a = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        let b = Matrix::new([1, 2, 3, 4], 2, 2);
+        let c = multiply(&a, &b);
+        assert!(c.is_err())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_a_can_not_multiply_b_panic() {
+        let a = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        let b = Matrix::new([1, 2, 3, 4], 2, 2);
+
+        let _c = a * b;
+    }
+
+    #[test]
+    fn test_multiply_with_threads() -> Result<()> {
+        let a = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        let b = Matrix::new([1, 2, 3, 4, 5, 6], 3, 2);
+
+        for num_threads in [1, 2, 8] {
+            let c = multiply_with_threads(&a, &b, num_threads)?;
+            assert_eq!(c.data, vec![22, 28, 49, 64]);
+        }
+
+        assert!(multiply_with_threads(&a, &b, 0).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_par_ranges_shorter_than_unit() {
+        let len = PARALLEL_THRESHOLD + 1;
+        let ranges = par_ranges(len, len + 1, |range| range);
+        assert_eq!(ranges, vec![0..len]);
+
+        // 按 unit 切分，每段都是 unit 的整数倍，合起来正好覆盖 0..len
+        let ranges = par_ranges(len, 3, |range| range);
+        assert!(ranges.len() <= NUM_THREADS);
+        assert!(ranges.iter().all(|r| r.start % 3 == 0));
+        assert_eq!(ranges.first().map(|r| r.start), Some(0));
+        assert_eq!(ranges.last().map(|r| r.end), Some(len));
+        assert!(ranges.windows(2).all(|w| w[0].end == w[1].start));
+    }
+
+    #[test]
+    fn test_multiply_batch() -> Result<()> {
+        let pairs = (0..10)
+            .map(|i| {
+                let a = Matrix::new([i, 2, 3, 4], 2, 2);
+                let b = Matrix::new([1, 2, 3, 4], 2, 2);
+                (a, b)
+            })
+            .collect::<Vec<_>>();
+
+        let results = multiply_batch(&pairs);
+        assert_eq!(results.len(), 10);
+
+        for (i, result) in results.into_iter().enumerate() {
+            let expected = multiply(&pairs[i].0, &pairs[i].1)?;
+            assert_eq!(result?.data, expected.data);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiply_batch_reports_errors_per_pair() {
+        let pairs = vec![
+            (
+                Matrix::new([1, 2, 3, 4], 2, 2),
+                Matrix::new([1, 2, 3, 4], 2, 2),
+            ),
+            (
+                Matrix::new([1, 2, 3, 4, 5, 6], 2, 3),
+                Matrix::new([1, 2, 3, 4], 2, 2),
+            ),
+            (Matrix::new([1, 2, 3], 1, 3), Matrix::new([1, 2, 3], 3, 1)),
+        ];
+
+        let results = multiply_batch(&pairs);
+
+        assert_eq!(results[0].as_ref().unwrap().data, vec![7, 10, 15, 22]);
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap().data, vec![14]);
+    }
+
+    #[test]
+    fn test_multiply_batch_empty() {
+        let pairs: Vec<(Matrix<i32>, Matrix<i32>)> = Vec::new();
+        assert!(multiply_batch(&pairs).is_empty());
+
+        let pairs = vec![(
+            Matrix::new([1, 2, 3, 4], 2, 2),
+            Matrix::new([1, 2, 3, 4], 2, 2),
+        )];
+        let results = multiply_batch(&pairs);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].as_ref().unwrap().data, vec![7, 10, 15, 22]);
+    }
+
+    #[test]
+    fn test_matrix_mul_vec() -> Result<()> {
+        let m = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        let v = Vector::new([1, 2, 3]);
+
+        let r = m.mul_vec(&v)?;
+        assert_eq!(*r, vec![14, 32]);
+
+        let r = &m * &v;
+        assert_eq!(*r, vec![14, 32]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_mul_vec_matches_multiply() -> Result<()> {
+        // 行数多于线程数，并且不能被整除，覆盖到最后一块不满的情况
+        let row = 11;
+        let col = 7;
+        let m = Matrix::new((0..(row * col) as i64).collect::<Vec<_>>(), row, col);
+        let v = (0..col as i64).collect::<Vec<_>>();
+
+        let expected = multiply(&m, &Matrix::new(v.clone(), col, 1))?;
+        let r = m.mul_vec(&Vector::new(v))?;
+        assert_eq!(*r, expected.data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_vector_mul_mat() -> Result<()> {
+        let row = 9;
+        let col = 5;
+        let m = Matrix::new((0..(row * col) as i64).collect::<Vec<_>>(), row, col);
+        let v = (0..row as i64).collect::<Vec<_>>();
+
+        let expected = multiply(&Matrix::new(v.clone(), 1, row), &m)?;
+        let r = Vector::new(v).mul_mat(&m)?;
+        assert_eq!(*r, expected.data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mul_vec_and_mul_mat_parallel() -> Result<()> {
+        // 元素个数超过 PARALLEL_THRESHOLD，走多线程的路径
+        let row = 201;
+        let col = 101;
+        assert!(row * col >= PARALLEL_THRESHOLD);
+        let m = Matrix::new((0..(row * col) as i64).collect::<Vec<_>>(), row, col);
+
+        let v = (0..col as i64).collect::<Vec<_>>();
+        let expected = multiply(&m, &Matrix::new(v.clone(), col, 1))?;
+        assert_eq!(*m.mul_vec(&Vector::new(v))?, expected.data);
+
+        let v = (0..row as i64).collect::<Vec<_>>();
+        let expected = multiply(&Matrix::new(v.clone(), 1, row), &m)?;
+        assert_eq!(*Vector::new(v).mul_mat(&m)?, expected.data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mul_vec_dimension_mismatch() {
+        let m = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        assert!(m.mul_vec(&Vector::new([1, 2])).is_err());
+        assert!(Vector::new([1, 2, 3]).mul_mat(&m).is_err());
+    }
+}
@@ -0,0 +1,325 @@
+use super::Matrix;
+use anyhow::{anyhow, Result};
+use std::fmt::{Debug, Display, Formatter};
+use std::ops::Range;
+
+/// 借用的子矩阵视图，不复制 data
+/// 第 i 行从 data[i * stride] 开始，连续 col 个元素
+/// stride 是原矩阵的列数，视图只是在原来的行上取了一段
+pub struct MatrixView<'a, T> {
+    data: &'a [T],
+    row: usize,
+    col: usize,
+    stride: usize,
+}
+
+impl<T> Matrix<T> {
+    /// (row, col)
+    pub fn shape(&self) -> (usize, usize) {
+        (self.row, self.col)
+    }
+
+    /// 整个矩阵的视图
+    pub fn view(&self) -> MatrixView<'_, T> {
+        MatrixView {
+            data: &self.data,
+            row: self.row,
+            col: self.col,
+            stride: self.col,
+        }
+    }
+
+    /// 取出 rows x cols 的子矩阵，返回借用的视图
+    pub fn submatrix(&self, rows: Range<usize>, cols: Range<usize>) -> Result<MatrixView<'_, T>> {
+        self.view().submatrix(rows, cols)
+    }
+
+    /// 按行把矩阵切成最多 n 份，用来把数据分给多个 worker
+    /// 前面的分片行数可能比后面多一行，n 为 0 时当作 1
+    /// 每一份都是连续的几行，视图直接指向原来的 data
+    pub fn split_rows(&self, n: usize) -> Vec<MatrixView<'_, T>> {
+        let n = n.max(1);
+        let base = self.row / n;
+        let extra = self.row % n;
+
+        let mut views = Vec::with_capacity(n);
+        let mut start = 0;
+        for i in 0..n {
+            let len = base + usize::from(i < extra);
+            if len == 0 {
+                break;
+            }
+
+            views.push(MatrixView {
+                data: &self.data[start * self.col..(start + len) * self.col],
+                row: len,
+                col: self.col,
+                stride: self.col,
+            });
+            start += len;
+        }
+
+        views
+    }
+
+    /// 改变形状，元素个数必须相同；直接复用原来的 data，不复制
+    pub fn reshape(self, row: usize, col: usize) -> Result<Matrix<T>> {
+        // row * col 溢出时也不可能和元素个数相同
+        if row.checked_mul(col) != Some(self.data.len()) {
+            return Err(anyhow!(
+                "matrix reshape error: can not reshape {}x{} into {}x{}",
+                self.row,
+                self.col,
+                row,
+                col
+            ));
+        }
+
+        Ok(Matrix {
+            data: self.data,
+            row,
+            col,
+        })
+    }
+}
+
+impl<T: Copy> Matrix<T> {
+    /// 左右拼接，每个矩阵的行数必须相同
+    pub fn hstack(matrices: &[&Matrix<T>]) -> Result<Matrix<T>> {
+        let row = match matrices.first() {
+            Some(m) => m.row,
+            None => return Ok(Matrix::new(vec![], 0, 0)),
+        };
+
+        if matrices.iter().any(|m| m.row != row) {
+            return Err(anyhow!("matrix hstack error: row mismatch"));
+        }
+
+        let col = matrices.iter().map(|m| m.col).sum();
+        let mut data = Vec::with_capacity(row * col);
+        for i in 0..row {
+            for m in matrices {
+                data.extend_from_slice(&m.data[i * m.col..(i + 1) * m.col]);
+            }
+        }
+
+        Ok(Matrix { data, row, col })
+    }
+
+    /// 上下拼接，每个矩阵的列数必须相同
+    pub fn vstack(matrices: &[&Matrix<T>]) -> Result<Matrix<T>> {
+        let col = match matrices.first() {
+            Some(m) => m.col,
+            None => return Ok(Matrix::new(vec![], 0, 0)),
+        };
+
+        if matrices.iter().any(|m| m.col != col) {
+            return Err(anyhow!("matrix vstack error: col mismatch"));
+        }
+
+        let row = matrices.iter().map(|m| m.row).sum();
+        let mut data = Vec::with_capacity(row * col);
+        for m in matrices {
+            data.extend_from_slice(&m.data);
+        }
+
+        Ok(Matrix { data, row, col })
+    }
+}
+
+impl<'a, T> MatrixView<'a, T> {
+    /// (row, col)
+    pub fn shape(&self) -> (usize, usize) {
+        (self.row, self.col)
+    }
+
+    /// 第 i 行第 j 列的元素，越界返回 None
+    pub fn get(&self, i: usize, j: usize) -> Option<&'a T> {
+        if i >= self.row || j >= self.col {
+            return None;
+        }
+
+        self.data.get(i * self.stride + j)
+    }
+
+    /// 第 i 行，每一行在原 data 中都是连续的，可以直接返回切片
+    pub fn row_slice(&self, i: usize) -> Option<&'a [T]> {
+        if i >= self.row {
+            return None;
+        }
+
+        let start = i * self.stride;
+        self.data.get(start..start + self.col)
+    }
+
+    /// 按顺序遍历每一行
+    pub fn rows(&self) -> impl Iterator<Item = &'a [T]> + '_ {
+        (0..self.row).filter_map(|i| self.row_slice(i))
+    }
+
+    /// 在视图上再取子视图，范围是相对于当前视图的
+    pub fn submatrix(&self, rows: Range<usize>, cols: Range<usize>) -> Result<MatrixView<'a, T>> {
+        if rows.start > rows.end || rows.end > self.row {
+            return Err(anyhow!("matrix submatrix error: row range out of bounds"));
+        }
+        if cols.start > cols.end || cols.end > self.col {
+            return Err(anyhow!("matrix submatrix error: col range out of bounds"));
+        }
+
+        let row = rows.end - rows.start;
+        let col = cols.end - cols.start;
+
+        // 只保留从第一个元素到最后一个元素之间的数据，空视图不引用任何数据
+        let data = if row == 0 || col == 0 {
+            &self.data[..0]
+        } else {
+            let start = rows.start * self.stride + cols.start;
+            let end = (rows.end - 1) * self.stride + cols.end;
+            &self.data[start..end]
+        };
+
+        Ok(MatrixView {
+            data,
+            row,
+            col,
+            stride: self.stride,
+        })
+    }
+}
+
+impl<T: Copy> MatrixView<'_, T> {
+    /// 复制成一个独立的矩阵
+    pub fn to_matrix(&self) -> Matrix<T> {
+        let mut data = Vec::with_capacity(self.row * self.col);
+        for row in self.rows() {
+            data.extend_from_slice(row);
+        }
+
+        Matrix::new(data, self.row, self.col)
+    }
+}
+
+impl<T: Display> Display for MatrixView<'_, T> {
+    // 和 Matrix 的格式一样
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{")?;
+        for (i, row) in self.rows().enumerate() {
+            for (j, x) in row.iter().enumerate() {
+                write!(f, "{}", x)?;
+                if j != self.col - 1 {
+                    write!(f, " ")?;
+                }
+            }
+
+            if i != self.row - 1 {
+                write!(f, ", ")?;
+            }
+        }
+        write!(f, "}}")?;
+        Ok(())
+    }
+}
+
+impl<T: Display> Debug for MatrixView<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "MatrixView(row={}, col={}, {})",
+            self.row, self.col, self
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix_3x4() -> Matrix<i32> {
+        Matrix::new([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12], 3, 4)
+    }
+
+    #[test]
+    fn test_submatrix() -> Result<()> {
+        let m = matrix_3x4();
+        let v = m.submatrix(1..3, 1..3)?;
+
+        assert_eq!(v.shape(), (2, 2));
+        assert_eq!(v.get(0, 0), Some(&6));
+        assert_eq!(v.get(1, 1), Some(&11));
+        assert_eq!(v.get(2, 0), None);
+        assert_eq!(v.row_slice(1), Some(&[10, 11][..]));
+        assert_eq!(format!("{:?}", v), "MatrixView(row=2, col=2, {6 7, 10 11})");
+
+        // 视图指向原来的 data，没有复制
+        assert!(std::ptr::eq(v.get(0, 0).unwrap(), &m.data[5]));
+
+        let sub = v.submatrix(1..2, 0..2)?;
+        assert_eq!(sub.to_matrix().data, vec![10, 11]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_submatrix_out_of_bounds() {
+        let m = matrix_3x4();
+        assert!(m.submatrix(0..4, 0..1).is_err());
+        assert!(m.submatrix(0..1, 2..5).is_err());
+
+        let empty = m.submatrix(1..1, 0..4).unwrap();
+        assert_eq!(empty.shape(), (0, 4));
+        assert_eq!(empty.to_matrix().data, Vec::<i32>::new());
+    }
+
+    #[test]
+    fn test_hstack_vstack() -> Result<()> {
+        let a = Matrix::new([1, 2, 3, 4], 2, 2);
+        let b = Matrix::new([5, 6], 2, 1);
+
+        let h = Matrix::hstack(&[&a, &b])?;
+        assert_eq!(h.shape(), (2, 3));
+        assert_eq!(h.data, vec![1, 2, 5, 3, 4, 6]);
+
+        let c = Matrix::new([7, 8], 1, 2);
+        let v = Matrix::vstack(&[&a, &c])?;
+        assert_eq!(v.shape(), (3, 2));
+        assert_eq!(v.data, vec![1, 2, 3, 4, 7, 8]);
+
+        assert!(Matrix::hstack(&[&a, &c]).is_err());
+        assert!(Matrix::vstack(&[&a, &b]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_rows() {
+        let m = Matrix::new((0..10).collect::<Vec<_>>(), 5, 2);
+
+        let shards = m.split_rows(3);
+        assert_eq!(
+            shards.iter().map(|v| v.shape()).collect::<Vec<_>>(),
+            vec![(2, 2), (2, 2), (1, 2)]
+        );
+
+        let parts = shards.iter().map(|v| v.to_matrix()).collect::<Vec<_>>();
+        let joined = Matrix::vstack(&parts.iter().collect::<Vec<_>>()).unwrap();
+        assert_eq!(joined.data, m.data);
+
+        // 分片数比行数多时，只返回非空的分片
+        assert_eq!(m.split_rows(8).len(), 5);
+        assert_eq!(m.split_rows(0).len(), 1);
+    }
+
+    #[test]
+    fn test_reshape() {
+        let m = matrix_3x4();
+        let ptr = m.data.as_ptr();
+
+        let r = m.reshape(2, 6).unwrap();
+        assert_eq!(r.shape(), (2, 6));
+        assert_eq!(r.data.as_ptr(), ptr);
+
+        assert!(r.reshape(5, 5).is_err());
+        // row * col 溢出时返回错误，而不是回绕之后碰巧相等
+        assert!(matrix_3x4().reshape(usize::MAX, 2).is_err());
+    }
+}
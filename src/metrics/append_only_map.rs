@@ -0,0 +1,185 @@
+use std::collections::hash_map::RandomState;
+use std::fmt::{Debug, Formatter};
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+// 第一张表最少的槽位数
+const MIN_CAPACITY: usize = 8;
+
+/// 只能插入、不能删除的 map，读取不加锁，也不分配内存
+///
+/// 由若干张开放寻址的表串起来，每张表的槽位是一个 OnceLock，写入一次之后就不会再变
+/// 一张表用掉一半的槽位后，新的 key 写到下一张两倍大小的表中，已经插入的 key 不会移动
+/// 所以读到的 &V 在整个 map 的生命周期内都有效，查找最多遍历 O(log n) 张表
+///
+/// 插入用一个 Mutex 串行化，保证同一个 key 只插入一次；插入是少见的操作，读不受影响
+pub(crate) struct AppendOnlyMap<V> {
+    hasher: RandomState,
+    head: Table<V>,
+    insert_lock: Mutex<()>,
+}
+
+// 一个槽位，写入一次 (key, value) 之后不再变化
+type Slot<V> = OnceLock<(Box<str>, V)>;
+
+struct Table<V> {
+    slots: Box<[Slot<V>]>,
+    // 已经用掉的槽位数，只在持有 insert_lock 时修改
+    len: AtomicUsize,
+    next: OnceLock<Box<Table<V>>>,
+}
+
+impl<V> AppendOnlyMap<V> {
+    /// capacity 是预计的 key 数量，第一张表按它分配，放得下时查找只需要访问一张表
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        let slots = (capacity * 2).max(MIN_CAPACITY).next_power_of_two();
+
+        Self {
+            hasher: RandomState::new(),
+            head: Table::new(slots),
+            insert_lock: Mutex::new(()),
+        }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&V> {
+        let hash = self.hasher.hash_one(key) as usize;
+        self.tables().find_map(|table| table.get(hash, key))
+    }
+
+    /// key 不存在时用 f 创建一个值插入，返回 map 中的值
+    pub(crate) fn get_or_insert_with(&self, key: &str, f: impl FnOnce() -> V) -> &V {
+        if let Some(value) = self.get(key) {
+            return value;
+        }
+
+        // 锁只用来串行化插入，里面没有数据，被 poison 也可以继续使用
+        let _guard = self.insert_lock.lock().unwrap_or_else(|e| e.into_inner());
+        // 拿到锁之前可能已经有其他线程插入了同一个 key
+        if let Some(value) = self.get(key) {
+            return value;
+        }
+
+        let hash = self.hasher.hash_one(key) as usize;
+        let mut table = &self.head;
+        while let Some(next) = table.next.get() {
+            table = next;
+        }
+
+        // 超过一半的槽位后开放寻址的探测会变长，换到下一张表
+        let len = table.len.load(Ordering::Relaxed);
+        if (len + 1) * 2 > table.slots.len() {
+            let next = Table::new(table.slots.len() * 2);
+            table = table.next.get_or_init(|| Box::new(next));
+        }
+
+        table.insert(hash, key, f())
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&str, &V)> {
+        self.tables().flat_map(|table| {
+            table
+                .slots
+                .iter()
+                .filter_map(|slot| slot.get().map(|(k, v)| (k.as_ref(), v)))
+        })
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    fn tables(&self) -> impl Iterator<Item = &Table<V>> {
+        std::iter::successors(Some(&self.head), |table| {
+            table.next.get().map(|next| next.as_ref())
+        })
+    }
+}
+
+impl<V: Debug> Debug for AppendOnlyMap<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<V> Table<V> {
+    fn new(slots: usize) -> Self {
+        Self {
+            slots: (0..slots).map(|_| OnceLock::new()).collect(),
+            len: AtomicUsize::new(0),
+            next: OnceLock::new(),
+        }
+    }
+
+    // 从 hash 对应的槽位开始往后找，遇到空槽位说明这张表里没有这个 key
+    fn get(&self, hash: usize, key: &str) -> Option<&V> {
+        let mask = self.slots.len() - 1;
+        let mut idx = hash & mask;
+
+        for _ in 0..self.slots.len() {
+            match self.slots[idx].get() {
+                Some((k, v)) if k.as_ref() == key => return Some(v),
+                Some(_) => idx = (idx + 1) & mask,
+                None => return None,
+            }
+        }
+
+        None
+    }
+
+    // 调用者持有 insert_lock，并且保证表里还有空的槽位
+    fn insert(&self, hash: usize, key: &str, value: V) -> &V {
+        let mask = self.slots.len() - 1;
+        let mut idx = hash & mask;
+        while self.slots[idx].get().is_some() {
+            idx = (idx + 1) & mask;
+        }
+
+        self.len.fetch_add(1, Ordering::Relaxed);
+        let (_, value) = self.slots[idx].get_or_init(|| (key.into(), value));
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_insert_and_grow() {
+        let map = AppendOnlyMap::with_capacity(2);
+        for i in 0..1_000 {
+            let key = format!("key.{}", i);
+            assert_eq!(*map.get_or_insert_with(&key, || i), i);
+        }
+
+        // 已经存在的 key 不会被覆盖
+        assert_eq!(*map.get_or_insert_with("key.7", || 0), 7);
+        for i in 0..1_000 {
+            assert_eq!(map.get(&format!("key.{}", i)), Some(&i));
+        }
+        assert_eq!(map.get("key.1000"), None);
+        assert_eq!(map.iter().count(), 1_000);
+        assert_eq!(map.values().sum::<usize>(), (0..1_000).sum::<usize>());
+    }
+
+    #[test]
+    fn test_concurrent_insert_same_keys() {
+        let map = AppendOnlyMap::with_capacity(0);
+
+        // 多个线程同时插入同样的 key，每个 key 只会插入一次
+        thread::scope(|s| {
+            for t in 0..4 {
+                let map = &map;
+                s.spawn(move || {
+                    for i in 0..500 {
+                        map.get_or_insert_with(&i.to_string(), || t);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(map.iter().count(), 500);
+    }
+}
@@ -1,45 +1,184 @@
+use super::append_only_map::AppendOnlyMap;
+use crate::handle::Slot;
+use crate::{CounterHandle, Metrics};
 use anyhow::{anyhow, Result};
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
-use std::sync::atomic::{AtomicI64, Ordering};
 use std::sync::Arc;
 
+/// 每个 metric 是一个预先注册好的 AtomicI64
+/// 也可以用 with_storage 选择 AtomicStorage::Striped，每个 metric 是一个分片的 StripedCounter
+///
+/// 运行中可以用 register 增加新的 key，或者用 auto_register 让写入未注册的 key 时自动注册
+/// key 存在一个只增不减的 AppendOnlyMap 中，已经注册过的 key 读写时不加锁，也不分配内存
+///
+/// 内存顺序：所有操作都用 Ordering::Relaxed
+/// 每个计数器是独立的，没有通过计数器去"发布"其他内存中的数据，只需要单个变量上的原子性
+/// 对同一个 key 的 fetch_add / swap 这类 read-modify-write 操作不会丢失更新
+/// 但不同 key 之间不保证先后顺序，比如先 inc(a) 再 inc(b)，另一个线程可能先看到 b 的变化
 #[derive(Debug)]
 pub struct AtomicMetrics {
-    data: Arc<HashMap<&'static str, AtomicI64>>,
+    data: Arc<AppendOnlyMap<Arc<Slot>>>,
+    // 新注册的 key 使用的存储方式
+    storage: AtomicStorage,
+    // 写入未注册的 key 时是自动注册还是返回错误
+    auto_register: bool,
+}
+
+/// AtomicMetrics 中每个 metric 的存储方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AtomicStorage {
+    /// 一个 AtomicI64，没有填充，不同 metric 的值可能落在同一个 cache line 上
+    /// 读写都只有一次原子操作，适合大多数场景
+    #[default]
+    Inline,
+    /// 一个 StripedCounter，每个线程写自己的分片，多线程频繁写同一个 key 时不会互相争抢
+    /// 读取需要遍历所有分片，set / reset 不是原子操作，见 StripedCounter 的说明
+    Striped,
 }
 
 impl AtomicMetrics {
-    // 一开始就要将 metric name 全部确定好
+    // 一开始注册好的 key 放在第一张表里，查找最快；之后注册的 key 见 register
     pub fn new(metric_names: &[&'static str]) -> AtomicMetrics {
-        let map = metric_names
-            .iter()
-            .map(|&name| (name, AtomicI64::new(0)))
-            // 这里也可以不写，让其从下面的 Arc::new()中去推断
-            .collect::<HashMap<_, _>>();
+        Self::with_storage(metric_names, AtomicStorage::Inline)
+    }
+
+    pub fn with_storage(metric_names: &[&'static str], storage: AtomicStorage) -> AtomicMetrics {
+        let map = AppendOnlyMap::with_capacity(metric_names.len());
+        for &name in metric_names {
+            map.get_or_insert_with(name, || Arc::new(Slot::new(storage)));
+        }
 
         AtomicMetrics {
             data: Arc::new(map),
+            storage,
+            auto_register: false,
         }
     }
 
+    /// 之后写入（inc / add / set / swap）未注册的 key 时自动注册，而不是返回错误
+    /// 只影响这个句柄以及之后从它 clone 出来的句柄；get 不会注册 key
+    pub fn auto_register(mut self) -> Self {
+        self.auto_register = true;
+        self
+    }
+
+    /// 注册一个新的 key，初始值是 0，key 已经存在时什么都不做
+    /// 所有 clone 出来的句柄都能看到新注册的 key
+    pub fn register(&self, key: impl AsRef<str>) {
+        self.data
+            .get_or_insert_with(key.as_ref(), || Arc::new(Slot::new(self.storage)));
+    }
+
     pub fn inc(&self, key: impl AsRef<str>) -> Result<()> {
-        let key = key.as_ref();
+        self.add(key, 1)
+    }
 
-        let counter = self
-            .data
-            .get(key)
-            .ok_or_else(|| anyhow!("key {} not found", key))?;
+    pub fn dec(&self, key: impl AsRef<str>) -> Result<()> {
+        self.add(key, -1)
+    }
 
-        counter.fetch_add(1, Ordering::Relaxed);
+    pub fn add(&self, key: impl AsRef<str>, delta: i64) -> Result<()> {
+        self.slot(key.as_ref())?.add(delta);
         Ok(())
     }
+
+    pub fn set(&self, key: impl AsRef<str>, value: i64) -> Result<()> {
+        self.slot(key.as_ref())?.set(value);
+        Ok(())
+    }
+
+    /// 将 key 的值清零
+    pub fn reset(&self, key: impl AsRef<str>) -> Result<()> {
+        self.set(key, 0)
+    }
+
+    /// 将所有 key 的值清零，每个 key 单独清零，不是一个原子操作
+    pub fn reset_all(&self) {
+        for counter in self.data.values() {
+            counter.set(0);
+        }
+    }
+
+    /// 设置新值并返回之前的值，读和写是一个原子操作，中间不会插入其他线程的写入
+    /// 比如 swap(key, 0) 可以用来取出这一段时间的增量并清零
+    /// Striped 存储时各个分片依次 swap，不是一个原子操作，但同样不会丢失并发的写入
+    pub fn swap(&self, key: impl AsRef<str>, value: i64) -> Result<i64> {
+        Ok(self.slot(key.as_ref())?.swap(value))
+    }
+
+    pub fn get(&self, key: impl AsRef<str>) -> Option<i64> {
+        self.data.get(key.as_ref()).map(|counter| counter.get())
+    }
+
+    /// 取出 key 的句柄，之后通过句柄读写不再查找 key
+    /// 和 inc 一样，未注册的 key 会返回错误，除非打开了 auto_register
+    pub fn counter(&self, key: impl AsRef<str>) -> Result<CounterHandle> {
+        Ok(CounterHandle::new(Arc::clone(self.slot(key.as_ref())?)))
+    }
+
+    /// 复制出当前所有 metrics 的值
+    /// 一致性：每个 key 的值是一次原子 load，但各个 key 是依次读取的
+    /// 所以 snapshot 不是某一时刻的全局快照，不同 key 之间可能看到不同时刻的值
+    pub fn snapshot(&self) -> HashMap<String, i64> {
+        self.data
+            .iter()
+            .map(|(key, counter)| (key.to_string(), counter.get()))
+            .collect()
+    }
+}
+
+impl AtomicMetrics {
+    // 只能操作注册过的 key，打开 auto_register 时未注册的 key 会先注册
+    fn slot(&self, key: &str) -> Result<&Arc<Slot>> {
+        if self.auto_register {
+            return Ok(self
+                .data
+                .get_or_insert_with(key, || Arc::new(Slot::new(self.storage))));
+        }
+
+        self.data
+            .get(key)
+            .ok_or_else(|| anyhow!("key {} not found", key))
+    }
+}
+
+impl Metrics for AtomicMetrics {
+    fn inc(&self, key: impl AsRef<str>) -> Result<()> {
+        AtomicMetrics::inc(self, key)
+    }
+
+    fn dec(&self, key: impl AsRef<str>) -> Result<()> {
+        AtomicMetrics::dec(self, key)
+    }
+
+    fn add(&self, key: impl AsRef<str>, delta: i64) -> Result<()> {
+        AtomicMetrics::add(self, key, delta)
+    }
+
+    fn set(&self, key: impl AsRef<str>, value: i64) -> Result<()> {
+        AtomicMetrics::set(self, key, value)
+    }
+
+    fn get(&self, key: impl AsRef<str>) -> Option<i64> {
+        AtomicMetrics::get(self, key)
+    }
+
+    fn counter(&self, key: impl AsRef<str>) -> Result<CounterHandle> {
+        AtomicMetrics::counter(self, key)
+    }
+
+    fn snapshot(&self) -> HashMap<String, i64> {
+        AtomicMetrics::snapshot(self)
+    }
 }
 
 impl Clone for AtomicMetrics {
     fn clone(&self) -> Self {
         AtomicMetrics {
             data: Arc::clone(&self.data),
+            storage: self.storage,
+            auto_register: self.auto_register,
         }
     }
 }
@@ -47,9 +186,198 @@ impl Clone for AtomicMetrics {
 impl Display for AtomicMetrics {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         for (key, value) in self.data.iter() {
-            writeln!(f, "{}: {}", key, value.load(Ordering::Relaxed))?;
+            writeln!(f, "{}: {}", key, value.get())?;
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_get_and_snapshot() -> Result<()> {
+        let metrics = AtomicMetrics::new(&["a", "b"]);
+        metrics.inc("a")?;
+        metrics.inc("a")?;
+
+        assert_eq!(metrics.get("a"), Some(2));
+        assert_eq!(metrics.get("b"), Some(0));
+        assert_eq!(metrics.get("c"), None);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot["a"], 2);
+        assert_eq!(snapshot["b"], 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dec_add_reset() -> Result<()> {
+        let metrics = AtomicMetrics::new(&["a", "b"]);
+        metrics.add("a", 10)?;
+        metrics.dec("a")?;
+        metrics.add("b", -5)?;
+        assert_eq!(metrics.get("a"), Some(9));
+        assert_eq!(metrics.get("b"), Some(-5));
+
+        metrics.reset("a")?;
+        assert_eq!(metrics.get("a"), Some(0));
+        assert_eq!(metrics.get("b"), Some(-5));
+
+        metrics.add("a", 3)?;
+        metrics.reset_all();
+        assert_eq!(metrics.get("a"), Some(0));
+        assert_eq!(metrics.get("b"), Some(0));
+
+        assert!(metrics.dec("c").is_err());
+        assert!(metrics.add("c", 1).is_err());
+        assert!(metrics.reset("c").is_err());
+        assert!(metrics.swap("c", 1).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_swap_does_not_lose_updates() {
+        const WRITERS: usize = 4;
+        const OPS: i64 = 10_000;
+
+        let metrics = AtomicMetrics::new(&["req"]);
+
+        // 一边写，一边不断 swap(0) 取出增量，所有增量加起来应该等于总的写入次数
+        let drained = thread::scope(|s| {
+            for _ in 0..WRITERS {
+                let metrics = metrics.clone();
+                s.spawn(move || {
+                    for _ in 0..OPS {
+                        metrics.inc("req").unwrap();
+                    }
+                });
+            }
+
+            let mut drained = 0;
+            for _ in 0..1_000 {
+                drained += metrics.swap("req", 0).unwrap();
+            }
+            drained
+        });
+
+        let rest = metrics.swap("req", 0).unwrap();
+        assert_eq!(drained + rest, OPS * WRITERS as i64);
+        assert_eq!(metrics.get("req"), Some(0));
+    }
+
+    #[test]
+    fn test_striped_storage() -> Result<()> {
+        const WRITERS: usize = 8;
+        const OPS: i64 = 10_000;
+
+        let metrics = AtomicMetrics::with_storage(&["req", "in_flight"], AtomicStorage::Striped);
+        thread::scope(|s| {
+            for _ in 0..WRITERS {
+                let metrics = metrics.clone();
+                s.spawn(move || {
+                    for _ in 0..OPS {
+                        metrics.inc("req").unwrap();
+                        metrics.add("in_flight", 2).unwrap();
+                        metrics.dec("in_flight").unwrap();
+                    }
+                });
+            }
+        });
+
+        let total = OPS * WRITERS as i64;
+        assert_eq!(metrics.get("req"), Some(total));
+        assert_eq!(metrics.snapshot()["in_flight"], total);
+        assert_eq!(metrics.swap("req", 1)?, total);
+        assert_eq!(metrics.get("req"), Some(1));
+
+        metrics.reset_all();
+        assert_eq!(metrics.get("req"), Some(0));
+        assert_eq!(metrics.get("in_flight"), Some(0));
+        assert!(metrics.inc("c").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_at_runtime() -> Result<()> {
+        let metrics = AtomicMetrics::new(&["req.page.1"]);
+        assert!(metrics.inc("req.page.2").is_err());
+
+        // 注册之后所有句柄都能使用新的 key
+        let other = metrics.clone();
+        metrics.register("req.page.2");
+        other.inc("req.page.2")?;
+        metrics.register("req.page.2");
+        assert_eq!(metrics.get("req.page.2"), Some(1));
+
+        let auto = metrics.clone().auto_register();
+        auto.add("req.page.3", 5)?;
+        assert_eq!(metrics.get("req.page.3"), Some(5));
+        assert_eq!(auto.get("req.page.4"), None);
+        assert!(metrics.inc("req.page.4").is_err());
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.len(), 3);
+        assert_eq!(snapshot["req.page.1"], 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_counter_handle() -> Result<()> {
+        for storage in [AtomicStorage::Inline, AtomicStorage::Striped] {
+            let metrics = AtomicMetrics::with_storage(&["req"], storage);
+            assert!(metrics.counter("unknown").is_err());
+
+            // 句柄和 key 读写的是同一个值
+            let req = metrics.counter("req")?;
+            thread::scope(|s| {
+                for _ in 0..4 {
+                    let req = req.clone();
+                    s.spawn(move || {
+                        for _ in 0..1_000 {
+                            req.inc();
+                        }
+                    });
+                }
+            });
+            metrics.add("req", 2)?;
+            assert_eq!(req.get(), 4_002);
+            assert_eq!(req.swap(0), 4_002);
+            assert_eq!(metrics.get("req"), Some(0));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_auto_register() {
+        const WRITERS: usize = 4;
+        const PAGES: usize = 200;
+
+        let metrics = AtomicMetrics::with_storage(&[], AtomicStorage::Striped).auto_register();
+
+        // 多个线程同时写入同一批新 key，每个 key 只注册一次，写入都不会丢失
+        thread::scope(|s| {
+            for _ in 0..WRITERS {
+                let metrics = metrics.clone();
+                s.spawn(move || {
+                    for page in 0..PAGES {
+                        metrics.inc(format!("req.page.{}", page)).unwrap();
+                    }
+                });
+            }
+        });
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.len(), PAGES);
+        assert!(snapshot.values().all(|&v| v == WRITERS as i64));
+    }
+}
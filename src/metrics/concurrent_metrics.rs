@@ -1,60 +1,713 @@
-use anyhow::Result;
+use crate::handle::Slot;
+use crate::metrics::gauge_families;
+use crate::{
+    AtomicStorage, Clock, CounterHandle, FamilySnapshot, InFlight, Labels, LatencyHistogram,
+    LocalMetrics, Meter, MetricKind, MetricValue, Metrics, SystemClock, Timer,
+};
+use anyhow::{anyhow, Result};
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
+use std::collections::HashMap;
 use std::fmt::Display;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 
+/// 超过 key 数量上限之后，新 key 的写入都合并到这个 key 上
+/// 这个名字是保留的：add / set 等直接写入会返回错误，counter 等取出的句柄指向的就是这个合计
+pub const OVERFLOW_KEY: &str = "__overflow__";
+
+/// families 中 timer 的名字是 key 加上这个后缀，和同名的 counter 区分开，也标明单位是微秒
+pub const TIMER_SUFFIX: &str = ".us";
+
+/// families 中 timer 的 bucket 上界，单位是微秒，从 100us 到 10s
+pub const DEFAULT_TIMER_BOUNDS: [f64; 16] = [
+    100.0,
+    250.0,
+    500.0,
+    1_000.0,
+    2_500.0,
+    5_000.0,
+    10_000.0,
+    25_000.0,
+    50_000.0,
+    100_000.0,
+    250_000.0,
+    500_000.0,
+    1_000_000.0,
+    2_500_000.0,
+    5_000_000.0,
+    10_000_000.0,
+];
+
 /// metrics 的数据结构
 /// 如果加了 clone ，clone后，就完全是一个新的 metrics
 #[derive(Debug, Clone)] // clone 是对 Arc 进行 clone
 pub struct ConcurrentMetrics {
     // 锁相关的操作，全部被封装在 DashMap 的内部
-    data: Arc<DashMap<String, i64>>,
+    // 值是原子变量，DashMap 只用来找到它，这样 counter 返回的句柄可以绕过 DashMap 直接读写
+    data: Arc<DashMap<String, Arc<Slot>>>,
+    // 和 counter 同名的 Meter，计算最近 1 / 5 / 15 分钟的速率，总次数就是 counter 的值
+    meters: Arc<DashMap<String, Meter>>,
+    // timer 记录的耗时分布，和 counter 是两个独立的名字空间，但是一起计入 key 数量上限
+    histograms: Arc<DashMap<String, LatencyHistogram>>,
+    clock: Arc<dyn Clock>,
+    limit: Arc<KeyLimit>,
+}
+
+// key 数量的限制，防止调用者用不断变化的 key（比如带上用户 id）把 DashMap 撑爆
+#[derive(Debug)]
+struct KeyLimit {
+    max_keys: Option<usize>,
+    // data 和 histograms 中 key 的数量，和 DashMap::len 不同，这里的检查和增加是一个原子操作
+    keys: AtomicUsize,
+    // 因为超过上限而没有创建的 key 的次数
+    dropped: AtomicU64,
+    overflow: Arc<Slot>,
+    // 超过上限之后新的 timer 都记录到这里
+    overflow_histogram: LatencyHistogram,
 }
 
 impl ConcurrentMetrics {
     pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock::new()))
+    }
+
+    /// Meter 和 Timer 使用的时钟，测试时可以换成 ManualClock
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
         Self {
             data: Arc::new(DashMap::new()),
+            meters: Arc::new(DashMap::new()),
+            histograms: Arc::new(DashMap::new()),
+            clock,
+            limit: Arc::new(KeyLimit::new(None)),
+        }
+    }
+
+    /// 最多创建 max_keys 个 key，之后新 key 的写入都合并到 OVERFLOW_KEY 上，并且计入 dropped_keys
+    /// counter 和 timer 的 histogram 一起计数，超过上限的 timer 记录到 histogram(OVERFLOW_KEY) 中
+    /// 已经存在的 key 不受影响；需要在 clone 之前设置，clone 出来的 metrics 共享同一个限制
+    pub fn with_max_keys(mut self, max_keys: usize) -> Self {
+        let limit = KeyLimit::new(Some(max_keys));
+        limit
+            .keys
+            .store(self.data.len() + self.histograms.len(), Ordering::Relaxed);
+        self.limit = Arc::new(limit);
+        self
+    }
+
+    /// 因为超过 key 数量上限而没有创建的 key 的次数，同一个 key 被拒绝多次时每次都算
+    pub fn dropped_keys(&self) -> u64 {
+        self.limit.dropped.load(Ordering::Relaxed)
+    }
+
+    /// 删除一个 key，返回它最后的值，删除之后可以再创建新的 key
+    /// 同名的 timer histogram 也一起删除，返回值只是 counter 的值
+    /// 之前取出的 CounterHandle / Meter / LatencyHistogram 仍然可以写，但写入的值不会再出现在 metrics 中
+    pub fn remove(&self, key: impl AsRef<str>) -> Option<i64> {
+        let key = key.as_ref();
+        self.meters.remove(key);
+        if self.histograms.remove(key).is_some() {
+            self.limit.keys.fetch_sub(1, Ordering::Relaxed);
         }
+        let (_, slot) = self.data.remove(key)?;
+        self.limit.keys.fetch_sub(1, Ordering::Relaxed);
+
+        Some(slot.get())
     }
 
     // 任何东西，只要能够转换为 String 都可以作为 key
     pub fn inc(&self, key: impl Into<String>) -> Result<()> {
-        // `MutexGuard<'_, HashMap<String, i64>>` cannot be sent between threads safely
-        let mut counter = self
-            .data
-            // lock()? 返回的错误无法在线程之间安全的传递
-            .entry(key.into())
-            .or_insert(0);
+        self.add(key, 1)
+    }
 
-        *counter += 1;
+    pub fn dec(&self, key: impl Into<String>) -> Result<()> {
+        self.add(key, -1)
+    }
+
+    pub fn add(&self, key: impl Into<String>, delta: i64) -> Result<()> {
+        self.writable_slot(key.into())?.add(delta);
 
         Ok(())
     }
 
-    pub fn dec(&self, key: impl Into<String>) -> Result<()> {
-        let mut counter = self.data.entry(key.into()).or_insert(0);
-
-        *counter -= 1;
+    pub fn set(&self, key: impl Into<String>, value: i64) -> Result<()> {
+        self.writable_slot(key.into())?.set(value);
 
         Ok(())
     }
 
-    // pub fn snapshot(&self) -> Result<HashMap<String, i64>> {
-    //     Ok(self
-    //         .data
-    //         .read()
-    //         .map_err(|e| anyhow!(e.to_string()))?
-    //         .clone())
-    // }
+    pub fn get(&self, key: impl AsRef<str>) -> Option<i64> {
+        let key = key.as_ref();
+        if key == OVERFLOW_KEY {
+            return self.limit.overflow_value();
+        }
+
+        self.data.get(key).map(|slot| slot.get())
+    }
+
+    /// 取出 key 的句柄，key 不存在时插入 0
+    /// 之后通过句柄读写只是一次原子操作，不再分配 String，也不再经过 DashMap 的锁
+    pub fn counter(&self, key: impl Into<String>) -> CounterHandle {
+        CounterHandle::new(self.slot(key.into()))
+    }
+
+    /// 取出 key 对应的 Meter，不存在时创建
+    /// Meter 的 mark 会同时增加同名 counter 的值；直接 inc counter 不会影响 Meter 的速率
+    pub fn meter(&self, key: impl Into<String>) -> Meter {
+        let key = key.into();
+        if let Some(meter) = self.meters.get(&key) {
+            return meter.clone();
+        }
+
+        let counter = self.counter(key.clone());
+        // 超过上限时 counter 是 overflow，Meter 也不保存，否则 meters 同样会无限增长
+        if !self.data.contains_key(&key) {
+            return Meter::with_counter(counter, self.clock.clone());
+        }
+
+        self.meters
+            .entry(key)
+            .or_insert_with(|| Meter::with_counter(counter, self.clock.clone()))
+            .clone()
+    }
+
+    /// 取出 key 对应的 LatencyHistogram，不存在时创建，timer 记录的耗时就在这里
+    /// 新的 key 和 counter 一样计入 key 数量上限，超过上限时返回 OVERFLOW_KEY 的 histogram
+    pub fn histogram(&self, key: impl Into<String>) -> LatencyHistogram {
+        let key = key.into();
+        if key == OVERFLOW_KEY {
+            return self.limit.overflow_histogram.clone();
+        }
+
+        if let Some(histogram) = self.histograms.get(&key) {
+            return histogram.clone();
+        }
+
+        match self.histograms.entry(key) {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => {
+                if !self.limit.try_add_key() {
+                    return self.limit.overflow_histogram.clone();
+                }
+                entry.insert(LatencyHistogram::new()).clone()
+            }
+        }
+    }
+
+    /// 开始计时，返回的 Timer 被 drop 时把经过的时间（微秒）记录到 histogram(key) 中
+    pub fn timer(&self, key: impl Into<String>) -> Timer {
+        Timer::new(self.histogram(key), self.clock.clone())
+    }
+
+    /// 给 key 加 1，返回的 InFlight 被 drop 时减 1，包括 panic 栈展开时
+    pub fn in_flight(&self, key: impl Into<String>) -> InFlight {
+        InFlight::new(self.clone(), key.into())
+    }
+
+    /// 创建一个写入本地缓冲、批量合并回来的 LocalMetrics，每个线程一个，见 LocalMetrics 的说明
+    pub fn local(&self) -> LocalMetrics {
+        LocalMetrics::new(self.clone())
+    }
+
+    /// 复制出当前所有 metrics 的值
+    /// 一致性：每个 key 的值是原子读取的，但 DashMap 是分 shard 加锁的，遍历时一个 shard 一个 shard 地读
+    /// 所以 snapshot 不是某一时刻的全局快照，遍历期间其他线程的写入可能只有一部分被看到
+    /// 有 key 因为超过上限被拒绝过时，snapshot 中包括 OVERFLOW_KEY
+    /// timer 的 histogram 不在 snapshot 中，用 histogram(key) 或者 families（Reporter 和各种 exporter 用的就是它）
+    pub fn snapshot(&self) -> HashMap<String, i64> {
+        let mut snapshot = self
+            .data
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().get()))
+            .collect::<HashMap<_, _>>();
+        if let Some(value) = self.limit.overflow_value() {
+            snapshot.insert(OVERFLOW_KEY.to_string(), value);
+        }
+
+        snapshot
+    }
+
+    /// 把 other 当前的值合并到自己中，比如每个 worker 一个 ConcurrentMetrics，合并到一个新建的里面统一查看
+    /// 每个 key 的值相加（ConcurrentMetrics 不区分 counter 和 gauge，in_flight 这样的值相加也就是总数），
+    /// timer 的 histogram 按 bucket 相加；other 的 OVERFLOW_KEY 和 dropped_keys 也加到自己的上面
+    /// 按名字和种类区分 gauge 的合并策略用 MetricsRegistry::merge
+    /// 注意合并是把值加上去，重复合并同一个 other 会重复计算
+    pub fn merge(&self, other: &ConcurrentMetrics) {
+        for (key, value) in other.snapshot() {
+            if key == OVERFLOW_KEY {
+                self.limit.overflow.add(value);
+            } else {
+                self.slot(key).add(value);
+            }
+        }
+        self.limit
+            .dropped
+            .fetch_add(other.dropped_keys(), Ordering::Relaxed);
+
+        // 先复制出来，合并自己时不会在持有 DashMap 锁的时候再去插入
+        let histograms = other
+            .histograms
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect::<Vec<_>>();
+        for (key, histogram) in histograms {
+            self.histogram(key).merge(&histogram);
+        }
+        self.limit
+            .overflow_histogram
+            .merge(&other.limit.overflow_histogram);
+    }
+
+    /// 按名字排序的 metric family：每个 counter 是一个没有 label 的 gauge（同 Metrics::families），
+    /// 每个 timer 是一个名字为 key + TIMER_SUFFIX 的 histogram，单位是微秒，bucket 上界是 DEFAULT_TIMER_BOUNDS
+    /// 有 timer 因为超过上限被合并过时，也包括 OVERFLOW_KEY + TIMER_SUFFIX
+    pub fn families(&self) -> Vec<FamilySnapshot> {
+        let mut histograms = self
+            .histograms
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect::<Vec<_>>();
+        let overflow = &self.limit.overflow_histogram;
+        if overflow.count() > 0 {
+            histograms.push((OVERFLOW_KEY.to_string(), overflow.clone()));
+        }
+
+        let mut families = gauge_families(self.snapshot());
+        families.extend(
+            histograms
+                .into_iter()
+                .map(|(key, histogram)| FamilySnapshot {
+                    name: format!("{}{}", key, TIMER_SUFFIX),
+                    kind: MetricKind::Histogram,
+                    help: None,
+                    metrics: vec![(
+                        Labels::default(),
+                        MetricValue::Histogram(histogram.to_snapshot(&DEFAULT_TIMER_BOUNDS)),
+                    )],
+                }),
+        );
+        families.sort_by(|a, b| a.name.cmp(&b.name));
+
+        families
+    }
+
+    /// 每个 key 现在的值减去 earlier（之前 snapshot 的结果）中的值，earlier 中没有的 key 是现在的值
+    /// 之后被 remove 的 key 当成 0，结果是 earlier 中值的相反数
+    /// 值可以减少（比如 in_flight），所以结果可能是负数
+    pub fn diff(&self, earlier: &HashMap<String, i64>) -> HashMap<String, i64> {
+        let mut diff = self
+            .snapshot()
+            .into_iter()
+            .map(|(key, value)| {
+                let delta = value - earlier.get(&key).copied().unwrap_or(0);
+                (key, delta)
+            })
+            .collect::<HashMap<_, _>>();
+        for (key, value) in earlier {
+            diff.entry(key.clone()).or_insert(-value);
+        }
+
+        diff
+    }
+
+    // OVERFLOW_KEY 是保留的名字，不能直接写入
+    fn writable_slot(&self, key: String) -> Result<Arc<Slot>> {
+        if key == OVERFLOW_KEY {
+            return Err(anyhow!(
+                "{} is reserved for keys over the limit",
+                OVERFLOW_KEY
+            ));
+        }
+
+        Ok(self.slot(key))
+    }
+
+    // 持有 shard 锁的时间只有查找的这一段，读写值是在锁外面的原子操作
+    // OVERFLOW_KEY 对应的就是超过上限的合计，data 中永远不会有这个 key，get 和 snapshot 读到的是同一个值
+    fn slot(&self, key: String) -> Arc<Slot> {
+        if key == OVERFLOW_KEY {
+            return self.limit.overflow.clone();
+        }
+
+        // key 已经存在时只需要 shard 的读锁
+        if let Some(slot) = self.data.get(&key) {
+            return Arc::clone(&slot);
+        }
+
+        // `MutexGuard<'_, HashMap<String, i64>>` cannot be sent between threads safely
+        // lock()? 返回的错误无法在线程之间安全的传递
+        match self.data.entry(key) {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => {
+                if !self.limit.try_add_key() {
+                    return self.limit.overflow.clone();
+                }
+                entry
+                    .insert(Arc::new(Slot::new(AtomicStorage::Inline)))
+                    .clone()
+            }
+        }
+    }
+}
+
+impl KeyLimit {
+    fn new(max_keys: Option<usize>) -> Self {
+        Self {
+            max_keys,
+            keys: AtomicUsize::new(0),
+            dropped: AtomicU64::new(0),
+            overflow: Arc::new(Slot::new(AtomicStorage::Inline)),
+            overflow_histogram: LatencyHistogram::new(),
+        }
+    }
+
+    // 还没有达到上限时占用一个名额，达到上限时记一次 dropped
+    fn try_add_key(&self) -> bool {
+        let Some(max_keys) = self.max_keys else {
+            self.keys.fetch_add(1, Ordering::Relaxed);
+            return true;
+        };
+
+        let added = self
+            .keys
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |keys| {
+                (keys < max_keys).then_some(keys + 1)
+            })
+            .is_ok();
+        if !added {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+
+        added
+    }
+
+    // 从来没有 key 被拒绝、也没有通过句柄写入过时，OVERFLOW_KEY 不存在
+    fn overflow_value(&self) -> Option<i64> {
+        let value = self.overflow.get();
+        (self.dropped.load(Ordering::Relaxed) > 0 || value != 0).then_some(value)
+    }
+}
+
+impl Metrics for ConcurrentMetrics {
+    fn inc(&self, key: impl AsRef<str>) -> Result<()> {
+        ConcurrentMetrics::inc(self, key.as_ref())
+    }
+
+    fn dec(&self, key: impl AsRef<str>) -> Result<()> {
+        ConcurrentMetrics::dec(self, key.as_ref())
+    }
+
+    fn add(&self, key: impl AsRef<str>, delta: i64) -> Result<()> {
+        ConcurrentMetrics::add(self, key.as_ref(), delta)
+    }
+
+    fn set(&self, key: impl AsRef<str>, value: i64) -> Result<()> {
+        ConcurrentMetrics::set(self, key.as_ref(), value)
+    }
+
+    fn get(&self, key: impl AsRef<str>) -> Option<i64> {
+        ConcurrentMetrics::get(self, key)
+    }
+
+    fn counter(&self, key: impl AsRef<str>) -> Result<CounterHandle> {
+        Ok(ConcurrentMetrics::counter(self, key.as_ref()))
+    }
+
+    fn snapshot(&self) -> HashMap<String, i64> {
+        ConcurrentMetrics::snapshot(self)
+    }
+
+    fn families(&self) -> Vec<FamilySnapshot> {
+        ConcurrentMetrics::families(self)
+    }
+}
+
+impl Default for ConcurrentMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Display for ConcurrentMetrics {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for entry in self.data.iter() {
-            writeln!(f, "{}: {}", entry.key(), entry.value())?;
+            writeln!(f, "{}: {}", entry.key(), entry.value().get())?;
+        }
+        if let Some(value) = self.limit.overflow_value() {
+            writeln!(f, "{}: {}", OVERFLOW_KEY, value)?;
+        }
+
+        // timer 每个一行，和 families 中的名字一致
+        for entry in self.histograms.iter() {
+            write_timer(f, entry.key(), entry.value())?;
+        }
+        if self.limit.overflow_histogram.count() > 0 {
+            write_timer(f, OVERFLOW_KEY, &self.limit.overflow_histogram)?;
         }
 
         Ok(())
     }
 }
+
+fn write_timer(
+    f: &mut std::fmt::Formatter<'_>,
+    key: &str,
+    histogram: &LatencyHistogram,
+) -> std::fmt::Result {
+    writeln!(
+        f,
+        "{}{}: count={} p50={} p99={} max={}",
+        key,
+        TIMER_SUFFIX,
+        histogram.count(),
+        histogram.p50().unwrap_or(0),
+        histogram.p99().unwrap_or(0),
+        histogram.max().unwrap_or(0)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_get_and_snapshot() -> Result<()> {
+        let metrics = ConcurrentMetrics::new();
+        metrics.inc("a")?;
+        metrics.inc("a")?;
+        metrics.dec("b")?;
+
+        assert_eq!(metrics.get("a"), Some(2));
+        assert_eq!(metrics.get("b"), Some(-1));
+        assert_eq!(metrics.get("c"), None);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot["a"], 2);
+        assert_eq!(snapshot["b"], -1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_counter_handle() -> Result<()> {
+        let metrics = ConcurrentMetrics::new();
+        let req = metrics.counter("req");
+        assert_eq!(metrics.get("req"), Some(0));
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let req = req.clone();
+                s.spawn(move || {
+                    for _ in 0..1_000 {
+                        req.inc();
+                    }
+                });
+            }
+        });
+        metrics.inc("req")?;
+        assert_eq!(req.get(), 4_001);
+
+        // set 修改的是同一个值，句柄仍然有效
+        metrics.set("req", 10)?;
+        req.dec();
+        assert_eq!(metrics.get("req"), Some(9));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_meter_shares_counter() {
+        let clock = ManualClock::new();
+        let metrics = ConcurrentMetrics::with_clock(Arc::new(clock.clone()));
+
+        let meter = metrics.meter("req");
+        meter.mark_n(50);
+        metrics.meter("req").mark_n(50);
+        assert_eq!(metrics.get("req"), Some(100));
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(metrics.meter("req").one_minute_rate(), 20.0);
+    }
+
+    #[test]
+    fn test_max_keys() -> Result<()> {
+        let metrics = ConcurrentMetrics::new().with_max_keys(2);
+        metrics.inc("a")?;
+        metrics.inc("b")?;
+        assert_eq!(metrics.get(OVERFLOW_KEY), None);
+
+        // 超过上限的 key 合并到 OVERFLOW_KEY，已有的 key 照常写入
+        metrics.add("c", 3)?;
+        metrics.inc("d")?;
+        metrics.inc("a")?;
+        assert_eq!(metrics.get("c"), None);
+        assert_eq!(metrics.get("a"), Some(2));
+        assert_eq!(metrics.dropped_keys(), 2);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.len(), 3);
+        assert_eq!(snapshot[OVERFLOW_KEY], 4);
+
+        // 删除之后腾出名额
+        assert_eq!(metrics.remove("a"), Some(2));
+        assert_eq!(metrics.remove("a"), None);
+        metrics.inc("e")?;
+        assert_eq!(metrics.get("e"), Some(1));
+        metrics.meter("f").mark();
+        assert!(metrics.meters.get("f").is_none());
+        assert_eq!(metrics.dropped_keys(), 3);
+
+        // OVERFLOW_KEY 不能当成普通的 key 写入
+        assert!(metrics.inc(OVERFLOW_KEY).is_err());
+        assert!(metrics.set(OVERFLOW_KEY, 0).is_err());
+        assert_eq!(metrics.get(OVERFLOW_KEY), Some(5));
+
+        // 没有上限时也一样，不会出现 snapshot 中有、get 却读不到的 key
+        let metrics = ConcurrentMetrics::new();
+        assert!(metrics.add(OVERFLOW_KEY, 3).is_err());
+        assert_eq!(metrics.get(OVERFLOW_KEY), None);
+        assert!(metrics.snapshot().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_keys_with_timers() -> Result<()> {
+        let metrics = ConcurrentMetrics::new().with_max_keys(2);
+        metrics.inc("req")?;
+
+        // timer 和 counter 共用同一个上限，超过上限的 timer 都记录到 OVERFLOW_KEY 的 histogram 中
+        for id in 0..1_000 {
+            metrics.histogram(format!("user.{}", id)).record(id);
+        }
+        assert_eq!(metrics.histograms.len(), 1);
+        assert_eq!(metrics.dropped_keys(), 999);
+        assert_eq!(metrics.histogram("user.0").count(), 1);
+        assert_eq!(metrics.histogram(OVERFLOW_KEY).count(), 999);
+        metrics.inc("new")?;
+        assert_eq!(metrics.get("new"), None);
+
+        // 删除 timer 之后腾出名额
+        assert_eq!(metrics.remove("user.0"), None);
+        metrics.histogram("db.query");
+        assert_eq!(metrics.histograms.len(), 1);
+        assert!(metrics.histograms.contains_key("db.query"));
+        assert_eq!(metrics.dropped_keys(), 1_000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_families_and_display() -> Result<()> {
+        let metrics = ConcurrentMetrics::new().with_max_keys(2);
+        metrics.add("req", 3)?;
+        metrics.histogram("db.query").record(2_000);
+        metrics.histogram("dropped").record(50);
+
+        let families = metrics.families();
+        let names = families.iter().map(|f| f.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec!["__overflow__", "__overflow__.us", "db.query.us", "req"]
+        );
+        assert_eq!(families[3].kind, MetricKind::Gauge);
+        assert_eq!(families[3].metrics[0].1, MetricValue::Gauge(3));
+        assert_eq!(families[2].kind, MetricKind::Histogram);
+        match &families[2].metrics[0].1 {
+            MetricValue::Histogram(h) => {
+                assert_eq!(h.bounds, DEFAULT_TIMER_BOUNDS);
+                assert_eq!(h.count, 1);
+                assert_eq!(h.sum, 2_000.0);
+                // 2000us 落在 (1000, 2500] 中
+                assert_eq!(h.buckets[4], 1);
+            }
+            other => panic!("unexpected timer value: {:?}", other),
+        }
+
+        let display = format!("{}", metrics);
+        assert!(display.contains("req: 3\n"));
+        assert!(display.contains("db.query.us: count=1 p50=2000 p99=2000 max=2000\n"));
+        assert!(display.contains("__overflow__.us: count=1 p50=50 p99=50 max=50\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_keys_with_concurrent_writers() {
+        const MAX_KEYS: usize = 64;
+
+        let metrics = ConcurrentMetrics::new().with_max_keys(MAX_KEYS);
+
+        // 多个线程同时创建不同的 key，最终 key 的数量正好是上限，写入都没有丢失
+        thread::scope(|s| {
+            for idx in 0..4 {
+                let metrics = metrics.clone();
+                s.spawn(move || {
+                    for page in 0..100 {
+                        metrics.inc(format!("req.{}.{}", idx, page)).unwrap();
+                    }
+                });
+            }
+        });
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.len(), MAX_KEYS + 1);
+        assert_eq!(snapshot.values().sum::<i64>(), 400);
+        assert_eq!(metrics.dropped_keys(), (400 - MAX_KEYS) as u64);
+    }
+
+    #[test]
+    fn test_merge_and_diff() -> Result<()> {
+        let clock = ManualClock::new();
+        let workers = [
+            ConcurrentMetrics::with_clock(Arc::new(clock.clone())),
+            ConcurrentMetrics::with_clock(Arc::new(clock.clone())).with_max_keys(2),
+        ];
+        workers[0].add("req", 3)?;
+        workers[1].add("req", 5)?;
+        let _g = workers[0].in_flight("in_flight");
+        {
+            // timer 也占用一个名额
+            let _t = workers[1].timer("db.query");
+            clock.advance(Duration::from_millis(2));
+        }
+        workers[1].inc("dropped")?;
+        workers[1].histogram("dropped").record(7);
+        workers[0].histogram("db.query").record(1_000);
+
+        let total = ConcurrentMetrics::new();
+        for worker in &workers {
+            total.merge(worker);
+        }
+        let earlier = total.snapshot();
+        assert_eq!(earlier["req"], 8);
+        assert_eq!(earlier["in_flight"], 1);
+        assert_eq!(earlier[OVERFLOW_KEY], 1);
+        assert_eq!(total.dropped_keys(), 2);
+        let histogram = total.histogram("db.query");
+        assert_eq!(histogram.count(), 2);
+        assert_eq!(histogram.sum(), 3_000);
+        assert_eq!(total.histogram(OVERFLOW_KEY).sum(), 7);
+
+        total.add("req", 2)?;
+        total.dec("in_flight")?;
+        total.inc("new")?;
+        let diff = total.diff(&earlier);
+        assert_eq!(diff["req"], 2);
+        assert_eq!(diff["in_flight"], -1);
+        assert_eq!(diff["new"], 1);
+        assert_eq!(diff[OVERFLOW_KEY], 0);
+
+        // 被删除的 key 也在结果中，变化量是负的
+        total.remove("req");
+        let diff = total.diff(&earlier);
+        assert_eq!(diff["req"], -8);
+        assert_eq!(diff["in_flight"], -1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// 单调递增的计数器，只能增加，不能减少
+/// clone 是对 Arc 进行 clone，所有 clone 出来的 Counter 指向同一个值
+#[derive(Debug, Clone, Default)]
+pub struct Counter {
+    value: Arc<AtomicU64>,
+}
+
+impl Counter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, delta: u64) {
+        self.value.fetch_add(delta, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_counter() {
+        let counter = Counter::new();
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let counter = counter.clone();
+                s.spawn(move || {
+                    for _ in 0..1_000 {
+                        counter.inc();
+                    }
+                    counter.add(10);
+                });
+            }
+        });
+
+        assert_eq!(counter.get(), 4_040);
+    }
+}
@@ -0,0 +1,12 @@
+use crate::{FamilySnapshot, MetricsRegistry};
+
+/// 把 metrics 的快照编码成某种文本格式，比如 Prometheus / JSON / StatsD
+/// 输入是 MetricsRegistry::collect 的结果，exporter 不直接依赖 registry，同一份快照可以交给多个 exporter
+pub trait MetricsExporter: Send + Sync {
+    fn export(&self, families: &[FamilySnapshot]) -> String;
+
+    /// 直接对 registry 取一次快照并编码
+    fn export_registry(&self, registry: &MetricsRegistry) -> String {
+        self.export(&registry.collect())
+    }
+}
@@ -0,0 +1,129 @@
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// 可以增加也可以减少的整数 gauge，比如正在处理的请求数
+#[derive(Debug, Clone, Default)]
+pub struct Gauge {
+    value: Arc<AtomicI64>,
+}
+
+impl Gauge {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn dec(&self) {
+        self.add(-1);
+    }
+
+    pub fn add(&self, delta: i64) {
+        self.value.fetch_add(delta, Ordering::Relaxed);
+    }
+
+    pub fn set(&self, value: i64) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// f64 的 gauge，比如 CPU 使用率
+/// 标准库没有 AtomicF64，这里把 f64 的 bit pattern 存在 AtomicU64 里
+#[derive(Debug, Clone)]
+pub struct FloatGauge {
+    bits: Arc<AtomicU64>,
+}
+
+impl FloatGauge {
+    pub fn new() -> Self {
+        Self {
+            bits: Arc::new(AtomicU64::new(0f64.to_bits())),
+        }
+    }
+
+    pub fn set(&self, value: f64) {
+        self.bits.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    /// 没有 f64 的 fetch_add，用 CAS 循环实现：读出旧值，加上 delta，如果期间被其他线程改过就重试
+    pub fn add(&self, delta: f64) {
+        atomic_f64_add(&self.bits, delta);
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.bits.load(Ordering::Relaxed))
+    }
+}
+
+impl Default for FloatGauge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 对存放 f64 bit pattern 的 AtomicU64 做原子加法
+pub(crate) fn atomic_f64_add(bits: &AtomicU64, delta: f64) {
+    // fetch_update 内部就是 CAS 循环，闭包返回 Some 总会成功，所以忽略返回值
+    let _ = bits.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |old| {
+        Some((f64::from_bits(old) + delta).to_bits())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_gauge() {
+        let gauge = Gauge::new();
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let gauge = gauge.clone();
+                s.spawn(move || {
+                    for _ in 0..1_000 {
+                        gauge.inc();
+                        gauge.add(2);
+                        gauge.dec();
+                    }
+                });
+            }
+        });
+        assert_eq!(gauge.get(), 8_000);
+
+        gauge.set(-3);
+        assert_eq!(gauge.get(), -3);
+    }
+
+    #[test]
+    fn test_float_gauge() {
+        let gauge = FloatGauge::new();
+        assert_eq!(gauge.get(), 0.0);
+
+        gauge.set(1.5);
+        assert_eq!(gauge.get(), 1.5);
+
+        // 0.25 可以被 f64 精确表示，并发相加不会有舍入误差
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let gauge = gauge.clone();
+                s.spawn(move || {
+                    for _ in 0..1_000 {
+                        gauge.add(0.25);
+                    }
+                });
+            }
+        });
+        assert_eq!(gauge.get(), 1_001.5);
+
+        gauge.add(-2.0);
+        assert_eq!(gauge.get(), 999.5);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{Clock, ConcurrentMetrics, LatencyHistogram};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+/// 计时用的守卫，drop 时把从创建开始经过的时间以微秒为单位记录到 LatencyHistogram 中
+/// 由 ConcurrentMetrics::timer 创建，时间来自 ConcurrentMetrics 的时钟
+/// 用法是 `let _t = metrics.timer("db.query");`，函数返回、提前 return 或者 panic 时都会记录
+/// 注意不要写成 `let _ = metrics.timer(..)`，那样守卫会立刻被 drop，记录的时间是 0
+#[derive(Debug)]
+#[must_use = "Timer 被 drop 时才记录时间，需要用一个变量保存"]
+pub struct Timer {
+    // stop 之后是 None，drop 时不再记录
+    histogram: Option<LatencyHistogram>,
+    clock: Arc<dyn Clock>,
+    start: Duration,
+}
+
+/// 进行中的数量的守卫，创建时给 key 加 1，drop 时减 1
+/// 由 ConcurrentMetrics::in_flight 创建，比如统计正在处理的请求数
+/// 持有守卫的代码 panic 时，栈展开同样会 drop 守卫，所以数量不会只增不减
+/// （panic = "abort" 时进程直接退出，也就不存在这个问题）
+#[derive(Debug)]
+#[must_use = "InFlight 被 drop 时就会减 1，需要用一个变量保存"]
+pub struct InFlight {
+    metrics: ConcurrentMetrics,
+    key: String,
+}
+
+impl Timer {
+    pub(crate) fn new(histogram: LatencyHistogram, clock: Arc<dyn Clock>) -> Self {
+        let start = clock.now();
+        Self {
+            histogram: Some(histogram),
+            clock,
+            start,
+        }
+    }
+
+    /// 到现在为止经过的时间，不记录
+    pub fn elapsed(&self) -> Duration {
+        self.clock.now().saturating_sub(self.start)
+    }
+
+    /// 立刻记录并返回经过的时间，之后 drop 时不会再记录一次
+    pub fn stop(mut self) -> Duration {
+        self.record().unwrap_or_default()
+    }
+
+    fn record(&mut self) -> Option<Duration> {
+        let histogram = self.histogram.take()?;
+        let elapsed = self.elapsed();
+        histogram.record_duration(elapsed);
+        Some(elapsed)
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.record();
+    }
+}
+
+impl InFlight {
+    pub(crate) fn new(metrics: ConcurrentMetrics, key: String) -> Self {
+        if let Err(e) = metrics.inc(&key) {
+            warn!("inc in flight metric {} error: {}", key, e);
+        }
+        Self { metrics, key }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if let Err(e) = self.metrics.dec(&self.key) {
+            warn!("dec in flight metric {} error: {}", self.key, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ConcurrentMetrics, ManualClock};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_timer() {
+        let clock = ManualClock::new();
+        let metrics = ConcurrentMetrics::with_clock(Arc::new(clock.clone()));
+
+        {
+            let _t = metrics.timer("db.query");
+            clock.advance(Duration::from_millis(3));
+        }
+        let t = metrics.timer("db.query");
+        clock.advance(Duration::from_millis(5));
+        assert_eq!(t.elapsed(), Duration::from_millis(5));
+        // stop 之后 drop 不会再记录
+        assert_eq!(t.stop(), Duration::from_millis(5));
+
+        let histogram = metrics.histogram("db.query");
+        assert_eq!(histogram.count(), 2);
+        assert_eq!(histogram.sum(), 8_000);
+        assert_eq!(histogram.min(), Some(3_000));
+        // 计时不会创建同名的 counter
+        assert_eq!(metrics.get("db.query"), None);
+    }
+
+    #[test]
+    fn test_in_flight() {
+        let metrics = ConcurrentMetrics::new();
+
+        let g1 = metrics.in_flight("http.requests");
+        let g2 = metrics.in_flight("http.requests");
+        assert_eq!(metrics.get("http.requests"), Some(2));
+        drop(g1);
+        assert_eq!(metrics.get("http.requests"), Some(1));
+        drop(g2);
+        assert_eq!(metrics.get("http.requests"), Some(0));
+    }
+
+    #[test]
+    fn test_guards_released_on_panic() {
+        let clock = ManualClock::new();
+        let metrics = ConcurrentMetrics::with_clock(Arc::new(clock.clone()));
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _g = metrics.in_flight("http.requests");
+            let _t = metrics.timer("http.latency");
+            clock.advance(Duration::from_millis(1));
+            assert_eq!(metrics.get("http.requests"), Some(1));
+            panic!("handler failed");
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(metrics.get("http.requests"), Some(0));
+        assert_eq!(metrics.histogram("http.latency").count(), 1);
+    }
+
+    #[test]
+    fn test_in_flight_concurrent() {
+        let metrics = ConcurrentMetrics::new();
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1_000 {
+                        let _g = metrics.in_flight("jobs");
+                        let value = metrics.get("jobs").unwrap();
+                        assert!((1..=4).contains(&value));
+                    }
+                });
+            }
+        });
+
+        assert_eq!(metrics.get("jobs"), Some(0));
+    }
+}
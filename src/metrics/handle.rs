@@ -0,0 +1,98 @@
+use crate::{AtomicStorage, StripedCounter};
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+/// 一个 metric 的句柄，由 AtomicMetrics::counter 或者 ConcurrentMetrics::counter 返回
+///
+/// 句柄直接持有值所在的原子变量（Arc），之后的读写不再查找 key：没有哈希、没有分配、也不经过 DashMap 的锁
+/// 在循环中反复写同一个 key 时，先取一次句柄再使用
+/// 和通过 key 读写的是同一个值，clone 是对 Arc 进行 clone
+#[derive(Debug, Clone)]
+pub struct CounterHandle {
+    slot: Arc<Slot>,
+}
+
+// 一个 metric 的值，两种存储方式提供同样的操作
+#[derive(Debug)]
+pub(crate) enum Slot {
+    Inline(AtomicI64),
+    Striped(StripedCounter),
+}
+
+impl CounterHandle {
+    pub(crate) fn new(slot: Arc<Slot>) -> Self {
+        Self { slot }
+    }
+
+    pub fn inc(&self) {
+        self.slot.add(1)
+    }
+
+    pub fn dec(&self) {
+        self.slot.add(-1)
+    }
+
+    pub fn add(&self, delta: i64) {
+        self.slot.add(delta)
+    }
+
+    pub fn set(&self, value: i64) {
+        self.slot.set(value)
+    }
+
+    /// 设置新值并返回之前的值，见 AtomicMetrics::swap
+    pub fn swap(&self, value: i64) -> i64 {
+        self.slot.swap(value)
+    }
+
+    pub fn get(&self) -> i64 {
+        self.slot.get()
+    }
+}
+
+impl Display for CounterHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.get())
+    }
+}
+
+// 内存顺序都是 Ordering::Relaxed，原因见 AtomicMetrics 的说明
+impl Slot {
+    pub(crate) fn new(storage: AtomicStorage) -> Self {
+        match storage {
+            AtomicStorage::Inline => Slot::Inline(AtomicI64::new(0)),
+            AtomicStorage::Striped => Slot::Striped(StripedCounter::new()),
+        }
+    }
+
+    pub(crate) fn add(&self, delta: i64) {
+        match self {
+            Slot::Inline(v) => {
+                v.fetch_add(delta, Ordering::Relaxed);
+            }
+            Slot::Striped(v) => v.add(delta),
+        }
+    }
+
+    pub(crate) fn set(&self, value: i64) {
+        match self {
+            Slot::Inline(v) => v.store(value, Ordering::Relaxed),
+            Slot::Striped(v) => v.set(value),
+        }
+    }
+
+    pub(crate) fn swap(&self, value: i64) -> i64 {
+        match self {
+            Slot::Inline(v) => v.swap(value, Ordering::Relaxed),
+            Slot::Striped(v) => v.swap(value),
+        }
+    }
+
+    pub(crate) fn get(&self) -> i64 {
+        match self {
+            Slot::Inline(v) => v.load(Ordering::Relaxed),
+            Slot::Striped(v) => v.get(),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use crate::metrics::gauge::atomic_f64_add;
+use anyhow::{anyhow, Result};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// 默认的 bucket 上界，和 Prometheus 客户端的默认值一致，单位是秒
+pub const DEFAULT_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// 按预先配置的 bucket 上界统计分布
+/// record 只做两次原子操作（bucket 计数 + sum），不需要加锁
+/// 第 i 个 bucket 记录 `bounds[i-1] < value <= bounds[i]` 的次数，最后一个 bucket 记录大于所有上界的值
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    inner: Arc<HistogramInner>,
+}
+
+#[derive(Debug)]
+struct HistogramInner {
+    bounds: Vec<f64>,
+    // 长度为 bounds.len() + 1，最后一个是 +Inf
+    buckets: Vec<AtomicU64>,
+    // f64 的 bit pattern
+    sum: AtomicU64,
+}
+
+/// Histogram 某一时刻的值
+/// 一致性：各个 bucket 和 sum 是分别读取的，并发 record 时 sum 和 buckets 可能差几次 record
+/// count 由 buckets 求和得到，所以 count 和 buckets 总是对得上的
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramSnapshot {
+    pub bounds: Vec<f64>,
+    pub buckets: Vec<u64>,
+    pub count: u64,
+    pub sum: f64,
+}
+
+impl Histogram {
+    /// bounds 必须是有限的、严格递增的上界
+    pub fn new(bounds: &[f64]) -> Result<Self> {
+        if bounds.iter().any(|b| !b.is_finite()) {
+            return Err(anyhow!("histogram bounds must be finite"));
+        }
+
+        if bounds.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(anyhow!("histogram bounds must be strictly increasing"));
+        }
+
+        let buckets = (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect();
+
+        Ok(Self {
+            inner: Arc::new(HistogramInner {
+                bounds: bounds.to_vec(),
+                buckets,
+                sum: AtomicU64::new(0f64.to_bits()),
+            }),
+        })
+    }
+
+    /// NaN 没有办法放进任何一个 bucket，直接忽略
+    pub fn record(&self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+
+        // bounds 是有序的，用二分查找第一个 >= value 的上界
+        let idx = self.inner.bounds.partition_point(|&b| b < value);
+        self.inner.buckets[idx].fetch_add(1, Ordering::Relaxed);
+        atomic_f64_add(&self.inner.sum, value);
+    }
+
+    pub fn bounds(&self) -> &[f64] {
+        &self.inner.bounds
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let buckets = self
+            .inner
+            .buckets
+            .iter()
+            .map(|b| b.load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+
+        HistogramSnapshot {
+            bounds: self.inner.bounds.clone(),
+            count: buckets.iter().sum(),
+            buckets,
+            sum: f64::from_bits(self.inner.sum.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// 把另一个 histogram 的快照按 bucket 加到自己身上，bounds 必须相同
+    pub fn merge_snapshot(&self, other: &HistogramSnapshot) -> Result<()> {
+        check_bounds(&self.inner.bounds, &other.bounds)?;
+
+        for (bucket, &count) in self.inner.buckets.iter().zip(&other.buckets) {
+            if count > 0 {
+                bucket.fetch_add(count, Ordering::Relaxed);
+            }
+        }
+        atomic_f64_add(&self.inner.sum, other.sum);
+
+        Ok(())
+    }
+}
+
+impl HistogramSnapshot {
+    /// 按 bucket 相加，bounds 必须相同
+    pub fn merge(&mut self, other: &HistogramSnapshot) -> Result<()> {
+        check_bounds(&self.bounds, &other.bounds)?;
+
+        for (bucket, count) in self.buckets.iter_mut().zip(&other.buckets) {
+            *bucket += count;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+
+        Ok(())
+    }
+
+    /// 从 earlier 到现在新增的部分，bounds 必须相同
+    /// 有 bucket 比 earlier 小说明中间被重置过（比如进程重启），这时返回现在的值
+    pub fn diff(&self, earlier: &HistogramSnapshot) -> Result<HistogramSnapshot> {
+        check_bounds(&self.bounds, &earlier.bounds)?;
+
+        if self
+            .buckets
+            .iter()
+            .zip(&earlier.buckets)
+            .any(|(a, b)| a < b)
+        {
+            return Ok(self.clone());
+        }
+
+        Ok(HistogramSnapshot {
+            bounds: self.bounds.clone(),
+            buckets: self
+                .buckets
+                .iter()
+                .zip(&earlier.buckets)
+                .map(|(a, b)| a - b)
+                .collect(),
+            count: self.count - earlier.count,
+            sum: self.sum - earlier.sum,
+        })
+    }
+}
+
+fn check_bounds(bounds: &[f64], other: &[f64]) -> Result<()> {
+    if bounds != other {
+        return Err(anyhow!("histogram bounds mismatch"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_histogram_buckets() -> Result<()> {
+        let histogram = Histogram::new(&[1.0, 5.0, 10.0])?;
+
+        for value in [0.5, 1.0, 3.0, 5.0, 7.0, 100.0] {
+            histogram.record(value);
+        }
+
+        let snapshot = histogram.snapshot();
+        // 上界是包含的：1.0 落在第一个 bucket，5.0 落在第二个
+        assert_eq!(snapshot.buckets, vec![2, 2, 1, 1]);
+        assert_eq!(snapshot.count, 6);
+        assert_eq!(snapshot.sum, 116.5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_histogram_invalid_bounds() {
+        assert!(Histogram::new(&[1.0, 1.0]).is_err());
+        assert!(Histogram::new(&[2.0, 1.0]).is_err());
+        assert!(Histogram::new(&[1.0, f64::INFINITY]).is_err());
+        assert!(Histogram::new(&[]).is_ok());
+    }
+
+    #[test]
+    fn test_histogram_concurrent_record() -> Result<()> {
+        let histogram = Histogram::new(&DEFAULT_BUCKETS)?;
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let histogram = histogram.clone();
+                s.spawn(move || {
+                    for _ in 0..1_000 {
+                        histogram.record(0.5);
+                        histogram.record(20.0);
+                    }
+                });
+            }
+        });
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count, 8_000);
+        assert_eq!(snapshot.buckets[6], 4_000);
+        assert_eq!(snapshot.buckets[DEFAULT_BUCKETS.len()], 4_000);
+        assert_eq!(snapshot.sum, 4_000.0 * 20.5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_histogram_merge_and_diff() -> Result<()> {
+        let a = Histogram::new(&[1.0, 5.0])?;
+        let b = Histogram::new(&[1.0, 5.0])?;
+        a.record(0.5);
+        b.record(3.0);
+        b.record(10.0);
+
+        let earlier = a.snapshot();
+        let mut merged = a.snapshot();
+        merged.merge(&b.snapshot())?;
+        assert_eq!(merged.buckets, vec![1, 1, 1]);
+        assert_eq!(merged.count, 3);
+        assert_eq!(merged.sum, 13.5);
+
+        a.merge_snapshot(&b.snapshot())?;
+        assert_eq!(a.snapshot(), merged);
+
+        let diff = a.snapshot().diff(&earlier)?;
+        assert_eq!(diff.buckets, vec![0, 1, 1]);
+        assert_eq!(diff.count, 2);
+        assert_eq!(diff.sum, 13.0);
+        // 比 earlier 小时当作被重置过
+        assert_eq!(earlier.diff(&a.snapshot())?, earlier);
+
+        let other = Histogram::new(&[2.0])?.snapshot();
+        assert!(merged.merge(&other).is_err());
+        assert!(merged.diff(&other).is_err());
+        assert!(a.merge_snapshot(&other).is_err());
+
+        Ok(())
+    }
+}
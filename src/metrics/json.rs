@@ -0,0 +1,160 @@
+use crate::{FamilySnapshot, Labels, MetricKind, MetricValue, MetricsExporter};
+use serde_json::{json, Map, Value};
+
+/// 把 metrics 快照输出成 JSON 数组，每个元素是一个 metric family
+/// [{"name": "req", "type": "counter", "help": null, "metrics": [{"labels": {"page": "1"}, "value": 3}]}]
+/// histogram 的 metric 是 {"labels": {}, "count": 3, "sum": 2.5, "buckets": [{"le": 0.1, "count": 1}, ..., {"le": "+Inf", "count": 1}]}
+/// buckets 中的 count 是每个 bucket 自己的次数，不是累加的
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonExporter {
+    pretty: bool,
+}
+
+impl JsonExporter {
+    /// 一行输出，适合 HTTP 响应或者按行写日志
+    pub fn compact() -> Self {
+        Self { pretty: false }
+    }
+
+    /// 带缩进的多行输出，方便人看
+    pub fn pretty() -> Self {
+        Self { pretty: true }
+    }
+
+    pub fn to_value(&self, families: &[FamilySnapshot]) -> Value {
+        Value::Array(families.iter().map(family_to_json).collect())
+    }
+}
+
+impl MetricsExporter for JsonExporter {
+    fn export(&self, families: &[FamilySnapshot]) -> String {
+        let value = self.to_value(families);
+        if self.pretty {
+            // Value 序列化不会失败
+            serde_json::to_string_pretty(&value).unwrap_or_default()
+        } else {
+            value.to_string()
+        }
+    }
+}
+
+fn family_to_json(family: &FamilySnapshot) -> Value {
+    let metrics = family
+        .metrics
+        .iter()
+        .map(|(labels, value)| metric_to_json(labels, value))
+        .collect::<Vec<_>>();
+
+    json!({
+        "name": family.name,
+        "type": kind_name(family.kind),
+        "help": family.help,
+        "metrics": metrics,
+    })
+}
+
+// type 字段是给程序读的标识符，不用 MetricKind 的 Display（"float gauge" 中间有空格）
+fn kind_name(kind: MetricKind) -> &'static str {
+    match kind {
+        MetricKind::Counter => "counter",
+        MetricKind::Gauge => "gauge",
+        MetricKind::FloatGauge => "float_gauge",
+        MetricKind::Histogram => "histogram",
+    }
+}
+
+fn metric_to_json(labels: &Labels, value: &MetricValue) -> Value {
+    let labels = labels
+        .iter()
+        .map(|(k, v)| (k.to_string(), Value::from(v)))
+        .collect::<Map<_, _>>();
+
+    match value {
+        MetricValue::Counter(v) => json!({ "labels": labels, "value": v }),
+        MetricValue::Gauge(v) => json!({ "labels": labels, "value": v }),
+        // NaN 和无穷大没法用 JSON 的数字表示，serde_json 会输出 null
+        MetricValue::FloatGauge(v) => json!({ "labels": labels, "value": v }),
+        MetricValue::Histogram(h) => {
+            let mut buckets = h
+                .bounds
+                .iter()
+                .zip(&h.buckets)
+                .map(|(le, count)| json!({ "le": le, "count": count }))
+                .collect::<Vec<_>>();
+            if let Some(count) = h.buckets.last() {
+                buckets.push(json!({ "le": "+Inf", "count": count }));
+            }
+
+            json!({
+                "labels": labels,
+                "count": h.count,
+                "sum": h.sum,
+                "buckets": buckets,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MetricsRegistry;
+    use anyhow::Result;
+
+    #[test]
+    fn test_json_exporter() -> Result<()> {
+        let registry = MetricsRegistry::new();
+        registry.describe("req", "requests");
+        registry
+            .counter_with_labels("req", &[("page", "1")])?
+            .add(3);
+        registry.histogram("latency", &[1.0])?.record(0.5);
+
+        let expected = json!([
+            {
+                "name": "latency",
+                "type": "histogram",
+                "help": null,
+                "metrics": [{
+                    "labels": {},
+                    "count": 1,
+                    "sum": 0.5,
+                    "buckets": [{ "le": 1.0, "count": 1 }, { "le": "+Inf", "count": 0 }],
+                }],
+            },
+            {
+                "name": "req",
+                "type": "counter",
+                "help": "requests",
+                "metrics": [{ "labels": { "page": "1" }, "value": 3 }],
+            },
+        ]);
+        let exporter = JsonExporter::compact();
+        assert_eq!(exporter.to_value(&registry.collect()), expected);
+
+        let compact = exporter.export_registry(&registry);
+        assert!(!compact.contains('\n'));
+        assert_eq!(serde_json::from_str::<Value>(&compact)?, expected);
+
+        let pretty = JsonExporter::pretty().export_registry(&registry);
+        assert!(pretty.starts_with("[\n  {\n"));
+        assert_eq!(serde_json::from_str::<Value>(&pretty)?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_kind_names() -> Result<()> {
+        let registry = MetricsRegistry::new();
+        registry.gauge("in_flight")?.set(2);
+        registry.float_gauge("cpu")?.set(0.5);
+
+        let value = JsonExporter::compact().to_value(&registry.collect());
+        assert_eq!(value[0]["name"], "cpu");
+        assert_eq!(value[0]["type"], "float_gauge");
+        assert_eq!(value[0]["metrics"][0]["value"], 0.5);
+        assert_eq!(value[1]["type"], "gauge");
+
+        Ok(())
+    }
+}
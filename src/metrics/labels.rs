@@ -0,0 +1,109 @@
+use anyhow::{anyhow, Result};
+use std::fmt::{Display, Formatter};
+
+/// 一组 label（维度），按 label 的名字排序
+/// 同样的 label 不管传入的顺序如何，得到的 Labels 都相等，所以可以直接作为 map 的 key
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Labels(Vec<(String, String)>);
+
+/// metric 的名字加上一组 label，唯一确定一个 metric
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MetricKey {
+    pub name: String,
+    pub labels: Labels,
+}
+
+impl Labels {
+    /// label 的名字不能重复
+    pub fn new(labels: &[(&str, &str)]) -> Result<Self> {
+        let mut labels = labels
+            .iter()
+            .map(|&(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>();
+        labels.sort();
+
+        if labels.windows(2).any(|w| w[0].0 == w[1].0) {
+            return Err(anyhow!("duplicate label name"));
+        }
+
+        Ok(Self(labels))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .binary_search_by(|(k, _)| k.as_str().cmp(name))
+            .ok()
+            .map(|idx| self.0[idx].1.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+impl MetricKey {
+    pub fn new(name: impl Into<String>, labels: Labels) -> Self {
+        Self {
+            name: name.into(),
+            labels,
+        }
+    }
+}
+
+impl Display for Labels {
+    // {method="GET",page="1"}，没有 label 时什么都不输出
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+
+        write!(f, "{{")?;
+        for (i, (k, v)) in self.0.iter().enumerate() {
+            if i != 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}=\"{}\"", k, v)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl Display for MetricKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.name, self.labels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels_are_sorted() -> Result<()> {
+        let a = Labels::new(&[("page", "1"), ("method", "GET")])?;
+        let b = Labels::new(&[("method", "GET"), ("page", "1")])?;
+
+        assert_eq!(a, b);
+        assert_eq!(a.get("page"), Some("1"));
+        assert_eq!(a.get("host"), None);
+        assert_eq!(format!("{}", a), r#"{method="GET",page="1"}"#);
+
+        let key = MetricKey::new("req", a);
+        assert_eq!(format!("{}", key), r#"req{method="GET",page="1"}"#);
+        assert_eq!(
+            format!("{}", MetricKey::new("req", Labels::default())),
+            "req"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_label_name() {
+        assert!(Labels::new(&[("page", "1"), ("page", "2")]).is_err());
+    }
+}
@@ -0,0 +1,365 @@
+use crate::HistogramSnapshot;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// 每个 2 的幂区间 [2^k, 2^(k+1)) 再线性地分成 2^SUB_BUCKET_BITS 个 bucket
+// 相对误差不超过 1 / 2^SUB_BUCKET_BITS，也就是 1.6%
+const SUB_BUCKET_BITS: u32 = 6;
+const SUB_BUCKET_COUNT: usize = 1 << SUB_BUCKET_BITS;
+// 小于 SUB_BUCKET_COUNT 的值每个值一个 bucket，之后每个 2 的幂区间 SUB_BUCKET_COUNT 个 bucket
+const BUCKET_COUNT: usize = (64 - SUB_BUCKET_BITS as usize + 1) * SUB_BUCKET_COUNT;
+
+/// 记录延迟分布的直方图，可以查询 p50 / p90 / p99 / max
+/// bucket 是对数-线性划分的（类似 HdrHistogram），覆盖整个 u64 的范围，不需要预先配置上界
+/// record 只做几次原子操作，多个线程同时 record 不需要加锁
+/// clone 是对 Arc 进行 clone
+#[derive(Debug, Clone)]
+pub struct LatencyHistogram {
+    inner: Arc<LatencyHistogramInner>,
+}
+
+#[derive(Debug)]
+struct LatencyHistogramInner {
+    buckets: Box<[AtomicU64]>,
+    sum: AtomicU64,
+    min: AtomicU64,
+    max: AtomicU64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        let buckets = (0..BUCKET_COUNT).map(|_| AtomicU64::new(0)).collect();
+
+        Self {
+            inner: Arc::new(LatencyHistogramInner {
+                buckets,
+                sum: AtomicU64::new(0),
+                min: AtomicU64::new(u64::MAX),
+                max: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// 记录一个值，单位由调用者决定，同一个直方图里保持一致即可
+    pub fn record(&self, value: u64) {
+        let inner = &self.inner;
+        inner.buckets[bucket_index(value)].fetch_add(1, Ordering::Relaxed);
+        inner.sum.fetch_add(value, Ordering::Relaxed);
+        inner.min.fetch_min(value, Ordering::Relaxed);
+        inner.max.fetch_max(value, Ordering::Relaxed);
+    }
+
+    /// 以微秒为单位记录一段时间
+    pub fn record_duration(&self, duration: Duration) {
+        self.record(u64::try_from(duration.as_micros()).unwrap_or(u64::MAX));
+    }
+
+    pub fn count(&self) -> u64 {
+        self.inner
+            .buckets
+            .iter()
+            .map(|b| b.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn sum(&self) -> u64 {
+        self.inner.sum.load(Ordering::Relaxed)
+    }
+
+    /// 没有记录过任何值时返回 None
+    pub fn min(&self) -> Option<u64> {
+        (self.count() > 0).then(|| self.inner.min.load(Ordering::Relaxed))
+    }
+
+    pub fn max(&self) -> Option<u64> {
+        (self.count() > 0).then(|| self.inner.max.load(Ordering::Relaxed))
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        let count = self.count();
+        (count > 0).then(|| self.sum() as f64 / count as f64)
+    }
+
+    /// 第 q 百分位的值，q 的范围是 [0, 100]
+    /// 返回值所在 bucket 的上界（不超过记录过的最大值），所以结果不会比真实值小，最多大 1.6%
+    /// 没有记录过任何值时返回 None
+    pub fn percentile(&self, q: f64) -> Option<u64> {
+        let counts = self
+            .inner
+            .buckets
+            .iter()
+            .map(|b| b.load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+
+        // 用这次读到的 bucket 求总数，保证 rank 和下面的累加是一致的
+        let total = counts.iter().sum::<u64>();
+        if total == 0 {
+            return None;
+        }
+
+        let q = q.clamp(0.0, 100.0);
+        let rank = ((q / 100.0 * total as f64).ceil() as u64).max(1);
+        let max = self.inner.max.load(Ordering::Relaxed);
+
+        let mut seen = 0;
+        for (idx, count) in counts.into_iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(bucket_upper(idx).min(max));
+            }
+        }
+
+        Some(max)
+    }
+
+    pub fn p50(&self) -> Option<u64> {
+        self.percentile(50.0)
+    }
+
+    pub fn p90(&self) -> Option<u64> {
+        self.percentile(90.0)
+    }
+
+    pub fn p99(&self) -> Option<u64> {
+        self.percentile(99.0)
+    }
+
+    /// 把 other 的数据加到自己身上，比如每个 worker 一个直方图，最后合并起来查询
+    /// 两边的 bucket 划分完全相同，直接按 bucket 相加
+    pub fn merge(&self, other: &LatencyHistogram) {
+        let (inner, other) = (&self.inner, &other.inner);
+        for (bucket, other) in inner.buckets.iter().zip(other.buckets.iter()) {
+            let count = other.load(Ordering::Relaxed);
+            if count > 0 {
+                bucket.fetch_add(count, Ordering::Relaxed);
+            }
+        }
+
+        inner
+            .sum
+            .fetch_add(other.sum.load(Ordering::Relaxed), Ordering::Relaxed);
+        inner
+            .min
+            .fetch_min(other.min.load(Ordering::Relaxed), Ordering::Relaxed);
+        inner
+            .max
+            .fetch_max(other.max.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+
+    /// 换算成按 bounds 划分的 HistogramSnapshot，用来交给 Reporter 和各种 exporter
+    /// 每个内部 bucket 按它的上界放进 bounds 中对应的 bucket，
+    /// 所以 bound 附近的值可能被算到下一个 bucket 中，误差和分位数一样不超过 1.6%
+    /// bounds 的要求和 Histogram::new 一样，必须是有限的、严格递增的
+    pub fn to_snapshot(&self, bounds: &[f64]) -> HistogramSnapshot {
+        let mut buckets = vec![0; bounds.len() + 1];
+        for (idx, bucket) in self.inner.buckets.iter().enumerate() {
+            let count = bucket.load(Ordering::Relaxed);
+            if count > 0 {
+                let upper = bucket_upper(idx) as f64;
+                buckets[bounds.partition_point(|&b| b < upper)] += count;
+            }
+        }
+
+        HistogramSnapshot {
+            bounds: bounds.to_vec(),
+            count: buckets.iter().sum(),
+            buckets,
+            sum: self.sum() as f64,
+        }
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// value 所在 bucket 的下标
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKET_COUNT as u64 {
+        return value as usize;
+    }
+
+    // value 的最高位是第 msb 位，右移 shift 位后落在 [SUB_BUCKET_COUNT, 2 * SUB_BUCKET_COUNT)
+    let msb = 63 - value.leading_zeros();
+    let shift = msb - SUB_BUCKET_BITS;
+    let sub = (value >> shift) as usize - SUB_BUCKET_COUNT;
+
+    (shift as usize + 1) * SUB_BUCKET_COUNT + sub
+}
+
+// 下标为 idx 的 bucket 能放下的最大值
+fn bucket_upper(idx: usize) -> u64 {
+    if idx < SUB_BUCKET_COUNT {
+        return idx as u64;
+    }
+
+    let shift = (idx / SUB_BUCKET_COUNT - 1) as u32;
+    let sub = (idx % SUB_BUCKET_COUNT) as u64;
+    let lower = (SUB_BUCKET_COUNT as u64 + sub) << shift;
+
+    lower + ((1u64 << shift) - 1)
+}
+
+impl Display for LatencyHistogram {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "count: {}", self.count())?;
+
+        let values = [
+            ("min", self.min()),
+            ("p50", self.p50()),
+            ("p90", self.p90()),
+            ("p99", self.p99()),
+            ("max", self.max()),
+        ];
+        for (key, value) in values {
+            if let Some(value) = value {
+                writeln!(f, "{}: {}", key, value)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // 相对误差的上界
+    const MAX_ERROR: f64 = 1.0 / SUB_BUCKET_COUNT as f64;
+
+    fn assert_close(actual: u64, expected: u64) {
+        let error = (actual as f64 - expected as f64).abs() / expected as f64;
+        assert!(
+            actual >= expected && error <= MAX_ERROR,
+            "actual: {}, expected: {}, error: {}",
+            actual,
+            expected,
+            error
+        );
+    }
+
+    #[test]
+    fn test_bucket_index_and_upper() {
+        // 每个值都落在 [前一个 bucket 的上界 + 1, 自己 bucket 的上界] 之间
+        for value in (0..100_000).chain([u64::MAX / 3, u64::MAX - 1, u64::MAX]) {
+            let idx = bucket_index(value);
+            assert!(idx < BUCKET_COUNT);
+            assert!(value <= bucket_upper(idx));
+            if idx > 0 {
+                assert!(value > bucket_upper(idx - 1));
+            }
+        }
+        assert_eq!(bucket_index(u64::MAX), BUCKET_COUNT - 1);
+        assert_eq!(bucket_upper(BUCKET_COUNT - 1), u64::MAX);
+    }
+
+    #[test]
+    fn test_percentile_accuracy() {
+        let histogram = LatencyHistogram::new();
+        for value in 1..=100_000 {
+            histogram.record(value);
+        }
+
+        assert_eq!(histogram.count(), 100_000);
+        assert_eq!(histogram.min(), Some(1));
+        assert_eq!(histogram.max(), Some(100_000));
+        assert_close(histogram.p50().unwrap(), 50_000);
+        assert_close(histogram.p90().unwrap(), 90_000);
+        assert_close(histogram.p99().unwrap(), 99_000);
+        assert_eq!(histogram.percentile(100.0), Some(100_000));
+        assert_eq!(histogram.percentile(0.0), Some(1));
+        assert_eq!(histogram.mean(), Some(50_000.5));
+    }
+
+    #[test]
+    fn test_small_values_are_exact() {
+        let histogram = LatencyHistogram::new();
+        for value in [3, 3, 7, 10] {
+            histogram.record(value);
+        }
+
+        assert_eq!(histogram.p50(), Some(3));
+        assert_eq!(histogram.percentile(75.0), Some(7));
+        assert_eq!(histogram.p99(), Some(10));
+    }
+
+    #[test]
+    fn test_empty() {
+        let histogram = LatencyHistogram::new();
+        assert_eq!(histogram.count(), 0);
+        assert_eq!(histogram.p50(), None);
+        assert_eq!(histogram.min(), None);
+        assert_eq!(histogram.max(), None);
+        assert_eq!(histogram.mean(), None);
+        assert_eq!(format!("{}", histogram), "count: 0\n");
+    }
+
+    #[test]
+    fn test_concurrent_record_and_merge() {
+        let histograms = (0..4).map(|_| LatencyHistogram::new()).collect::<Vec<_>>();
+        let shared = LatencyHistogram::new();
+
+        thread::scope(|s| {
+            for (idx, histogram) in histograms.iter().enumerate() {
+                let shared = shared.clone();
+                s.spawn(move || {
+                    for value in 0..10_000u64 {
+                        let value = value * 4 + idx as u64 + 1;
+                        histogram.record(value);
+                        shared.record(value);
+                    }
+                });
+            }
+        });
+
+        let merged = LatencyHistogram::new();
+        for histogram in &histograms {
+            merged.merge(histogram);
+        }
+
+        assert_eq!(merged.count(), 40_000);
+        assert_eq!(merged.sum(), shared.sum());
+        assert_eq!(merged.min(), Some(1));
+        assert_eq!(merged.max(), Some(40_000));
+        for q in [50.0, 90.0, 99.0, 99.9] {
+            assert_eq!(merged.percentile(q), shared.percentile(q));
+        }
+        assert_close(merged.p99().unwrap(), 39_600);
+    }
+
+    #[test]
+    fn test_record_duration_and_display() {
+        let histogram = LatencyHistogram::new();
+        histogram.record_duration(Duration::from_millis(5));
+
+        assert_eq!(histogram.max(), Some(5_000));
+        assert_eq!(
+            format!("{}", histogram),
+            "count: 1\nmin: 5000\np50: 5000\np90: 5000\np99: 5000\nmax: 5000\n"
+        );
+    }
+
+    #[test]
+    fn test_to_snapshot() {
+        let histogram = LatencyHistogram::new();
+        for value in [5, 50, 100, 5_000, 1_000_000] {
+            histogram.record(value);
+        }
+
+        let snapshot = histogram.to_snapshot(&[10.0, 100.0, 1_000.0]);
+        // 小于 SUB_BUCKET_COUNT * 2 的值是精确的，100 落在上界为 100 的 bucket 中
+        assert_eq!(snapshot.buckets, vec![1, 2, 0, 2]);
+        assert_eq!(snapshot.count, 5);
+        assert_eq!(snapshot.sum, 1_005_155.0);
+
+        let empty = LatencyHistogram::new().to_snapshot(&[1.0]);
+        assert_eq!(empty.buckets, vec![0, 0]);
+        assert_eq!(empty.count, 0);
+    }
+}
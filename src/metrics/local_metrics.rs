@@ -0,0 +1,234 @@
+use crate::{ConcurrentMetrics, CounterHandle};
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::time::{Duration, Instant};
+
+// 默认的 flush 条件
+const DEFAULT_FLUSH_THRESHOLD: usize = 1024;
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+// 每写入这么多次才检查一次 flush_interval，Instant::now() 不用每次都调用
+const INTERVAL_CHECK_OPS: usize = 64;
+
+/// 每个线程自己持有的 metrics 缓冲，写入先累加在本地，再批量合并到共享的 ConcurrentMetrics 中
+/// 热路径上的 inc 只是一次本地 HashMap 的查找和加法，没有原子操作，也没有锁
+/// 本地的 HashMap 只在当前线程使用，不需要抗 HashDoS，用 FxHash 代替默认的 SipHash
+///
+/// 什么时候 flush：
+/// - 本地累计的写入次数达到 flush_threshold（默认 1024）
+/// - 距离上一次 flush 超过了 flush_interval（默认 100ms），每 64 次写入才检查一次
+/// - 调用 flush，或者 LocalMetrics 被 drop
+///
+/// snapshot 可能漏掉的数据：每个 LocalMetrics 从上一次 flush 之后的写入
+/// 对于一直在写的线程，最多是 flush_threshold 次写入，或者 flush_interval 之后再 64 次写入
+/// interval 只在写入时检查，没有后台线程，一个线程停止写入后，剩下的数据要等 flush 或者 drop 才能看到
+///
+/// 不会自动绑定到线程：只有在线程中创建，或者 move 进线程闭包的 LocalMetrics，才会在线程结束时 drop 并 flush
+/// 这时 join 之后所有数据都可以看到；在线程之外持有的（比如放在结构体里借给线程用），要自己调用 flush 或者 drop
+///
+/// 每个线程创建一个，不能在线程之间共享（方法需要 &mut self）
+#[derive(Debug)]
+pub struct LocalMetrics {
+    metrics: ConcurrentMetrics,
+    // key -> (共享 metric 的句柄, 还没有 flush 的增量)
+    // flush 之后保留 key 和句柄，只把增量清零，同一个 key 之后的写入不再分配内存
+    pending: HashMap<String, (CounterHandle, i64), BuildHasherDefault<FxHasher>>,
+    ops: usize,
+    last_flush: Instant,
+    flush_threshold: usize,
+    flush_interval: Duration,
+}
+
+impl LocalMetrics {
+    pub fn new(metrics: ConcurrentMetrics) -> Self {
+        Self {
+            metrics,
+            pending: HashMap::default(),
+            ops: 0,
+            last_flush: Instant::now(),
+            flush_threshold: DEFAULT_FLUSH_THRESHOLD,
+            flush_interval: DEFAULT_FLUSH_INTERVAL,
+        }
+    }
+
+    /// 本地累计多少次写入之后 flush，0 和 1 都表示每次写入都 flush
+    pub fn flush_threshold(mut self, threshold: usize) -> Self {
+        self.flush_threshold = threshold;
+        self
+    }
+
+    /// 写入时距离上一次 flush 超过 interval 就 flush
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
+    pub fn inc(&mut self, key: impl AsRef<str>) {
+        self.add(key, 1)
+    }
+
+    pub fn dec(&mut self, key: impl AsRef<str>) {
+        self.add(key, -1)
+    }
+
+    /// 只支持增量，set 这类覆盖的操作没法合并，直接用 ConcurrentMetrics
+    pub fn add(&mut self, key: impl AsRef<str>, delta: i64) {
+        let key = key.as_ref();
+        match self.pending.get_mut(key) {
+            Some((_, pending)) => *pending += delta,
+            None => {
+                let handle = self.metrics.counter(key);
+                self.pending.insert(key.to_string(), (handle, delta));
+            }
+        }
+
+        self.ops += 1;
+        if self.ops >= self.flush_threshold
+            || (self.ops % INTERVAL_CHECK_OPS == 0
+                && self.last_flush.elapsed() >= self.flush_interval)
+        {
+            self.flush();
+        }
+    }
+
+    /// 本地还没有 flush 的增量，不包括共享的 ConcurrentMetrics 中已有的值
+    pub fn pending(&self, key: impl AsRef<str>) -> i64 {
+        self.pending
+            .get(key.as_ref())
+            .map_or(0, |(_, pending)| *pending)
+    }
+
+    /// 把本地的增量合并到共享的 ConcurrentMetrics 中，每个 key 一次原子加法
+    pub fn flush(&mut self) {
+        for (handle, pending) in self.pending.values_mut() {
+            if *pending != 0 {
+                handle.add(*pending);
+                *pending = 0;
+            }
+        }
+
+        self.ops = 0;
+        self.last_flush = Instant::now();
+    }
+}
+
+impl Drop for LocalMetrics {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+// rustc 使用的 FxHash：每 8 个字节一次乘法和异或，比 SipHash 快很多，但是容易构造冲突，只适合不受外部输入控制的 key
+#[derive(Debug, Default, Clone, Copy)]
+struct FxHasher {
+    hash: u64,
+}
+
+const FX_SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+impl FxHasher {
+    fn add_to_hash(&mut self, word: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(FX_SEED);
+    }
+}
+
+impl Hasher for FxHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let mut word = [0; 8];
+            word.copy_from_slice(chunk);
+            self.add_to_hash(u64::from_le_bytes(word));
+        }
+
+        let rest = chunks.remainder();
+        if !rest.is_empty() {
+            let mut word = [0; 8];
+            word[..rest.len()].copy_from_slice(rest);
+            self.add_to_hash(u64::from_le_bytes(word));
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.add_to_hash(i as u64);
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.add_to_hash(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_flush_on_threshold_and_drop() {
+        let metrics = ConcurrentMetrics::new();
+        let mut local = LocalMetrics::new(metrics.clone())
+            .flush_threshold(3)
+            .flush_interval(Duration::from_secs(3_600));
+
+        local.inc("req");
+        local.add("bytes", 100);
+        assert_eq!(metrics.get("req"), Some(0));
+        assert_eq!(local.pending("req"), 1);
+
+        // 第 3 次写入触发 flush
+        local.inc("req");
+        assert_eq!(metrics.get("req"), Some(2));
+        assert_eq!(metrics.get("bytes"), Some(100));
+        assert_eq!(local.pending("req"), 0);
+
+        local.dec("req");
+        assert_eq!(metrics.get("req"), Some(2));
+        drop(local);
+        assert_eq!(metrics.get("req"), Some(1));
+    }
+
+    #[test]
+    fn test_flush_on_interval() {
+        let metrics = ConcurrentMetrics::new();
+        let mut local = LocalMetrics::new(metrics.clone())
+            .flush_threshold(usize::MAX)
+            .flush_interval(Duration::from_millis(10));
+
+        local.inc("req");
+        thread::sleep(Duration::from_millis(20));
+        // interval 只在写入时检查，而且每 INTERVAL_CHECK_OPS 次写入才检查一次
+        assert_eq!(metrics.get("req"), Some(0));
+        for _ in 2..INTERVAL_CHECK_OPS {
+            local.inc("req");
+        }
+        assert_eq!(metrics.get("req"), Some(0));
+        local.inc("req");
+        assert_eq!(metrics.get("req"), Some(INTERVAL_CHECK_OPS as i64));
+    }
+
+    #[test]
+    fn test_flush_at_thread_exit() {
+        const WRITERS: usize = 4;
+        const OPS: i64 = 10_000;
+
+        let metrics = ConcurrentMetrics::new();
+
+        // 每个线程一个 LocalMetrics，线程结束时 drop 并 flush，join 之后总数是准确的
+        thread::scope(|s| {
+            for _ in 0..WRITERS {
+                let mut local = metrics.local().flush_threshold(1_000);
+                s.spawn(move || {
+                    for _ in 0..OPS {
+                        local.inc("req");
+                    }
+                    local.inc("req");
+                });
+            }
+        });
+
+        assert_eq!(metrics.get("req"), Some((OPS + 1) * WRITERS as i64));
+    }
+}
@@ -0,0 +1,259 @@
+use crate::handle::Slot;
+use crate::{AtomicStorage, Clock, CounterHandle, SystemClock};
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// 每 5 秒把这段时间的次数合并到移动平均中，和 Unix 的 load average 一样
+const TICK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 记录事件发生的次数，并计算最近 1 / 5 / 15 分钟的速率（每秒次数）
+/// 速率是指数加权移动平均（EWMA），越近的数据权重越大，类似 Unix 的 load average
+///
+/// mark 只做原子加法，移动平均在 mark 或者读速率时按经过的时间补上，没有后台线程
+/// 第一个 5 秒结束之前速率都是 0
+/// clone 是对 Arc 进行 clone
+#[derive(Debug, Clone)]
+pub struct Meter {
+    inner: Arc<MeterInner>,
+}
+
+#[derive(Debug)]
+struct MeterInner {
+    clock: Arc<dyn Clock>,
+    // 总次数，ConcurrentMetrics::meter 创建的 Meter 和同名的 counter 共用这个值
+    count: CounterHandle,
+    // 还没有合并到移动平均中的次数
+    uncounted: AtomicU64,
+    start: Duration,
+    // 上一次合并的时间，距离 start 总是 TICK_INTERVAL 的整数倍
+    last_tick: AtomicU64,
+    rates: Mutex<[Ewma; 3]>,
+}
+
+// 一个时间窗口的移动平均
+#[derive(Debug, Clone, Copy)]
+struct Ewma {
+    alpha: f64,
+    rate: f64,
+    initialized: bool,
+}
+
+impl Meter {
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock::new()))
+    }
+
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        let count = CounterHandle::new(Arc::new(Slot::new(AtomicStorage::Inline)));
+        Self::with_counter(count, clock)
+    }
+
+    // 总次数记录在 count 中
+    pub(crate) fn with_counter(count: CounterHandle, clock: Arc<dyn Clock>) -> Self {
+        let start = clock.now();
+
+        Self {
+            inner: Arc::new(MeterInner {
+                clock,
+                count,
+                uncounted: AtomicU64::new(0),
+                start,
+                last_tick: AtomicU64::new(start.as_nanos() as u64),
+                rates: Mutex::new([Ewma::new(1), Ewma::new(5), Ewma::new(15)]),
+            }),
+        }
+    }
+
+    pub fn mark(&self) {
+        self.mark_n(1)
+    }
+
+    pub fn mark_n(&self, n: u64) {
+        // 先合并之前的时间段，这次的 n 算在当前时间段里
+        self.tick_if_necessary();
+        self.inner.uncounted.fetch_add(n, Ordering::Relaxed);
+        self.inner.count.add(n as i64);
+    }
+
+    pub fn count(&self) -> i64 {
+        self.inner.count.get()
+    }
+
+    pub fn one_minute_rate(&self) -> f64 {
+        self.rate(0)
+    }
+
+    pub fn five_minute_rate(&self) -> f64 {
+        self.rate(1)
+    }
+
+    pub fn fifteen_minute_rate(&self) -> f64 {
+        self.rate(2)
+    }
+
+    /// 从创建开始的平均速率
+    pub fn mean_rate(&self) -> f64 {
+        let elapsed = (self.inner.clock.now() - self.inner.start).as_secs_f64();
+        if elapsed > 0.0 {
+            self.count() as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    fn rate(&self, idx: usize) -> f64 {
+        self.tick_if_necessary();
+        self.rates()[idx].rate
+    }
+
+    fn rates(&self) -> std::sync::MutexGuard<'_, [Ewma; 3]> {
+        self.inner.rates.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // 经过了几个 TICK_INTERVAL 就合并几次，多个线程同时发现时只有 CAS 成功的那个线程去合并
+    fn tick_if_necessary(&self) {
+        let inner = &self.inner;
+        let interval = TICK_INTERVAL.as_nanos() as u64;
+        let now = inner.clock.now().as_nanos() as u64;
+        let last = inner.last_tick.load(Ordering::Relaxed);
+
+        let ticks = now.saturating_sub(last) / interval;
+        if ticks == 0 {
+            return;
+        }
+
+        let next = last + ticks * interval;
+        if inner
+            .last_tick
+            .compare_exchange(last, next, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+
+        // 第一个时间段的次数是 uncounted，之后的 ticks - 1 个时间段都没有事件
+        let count = inner.uncounted.swap(0, Ordering::Relaxed);
+        for ewma in self.rates().iter_mut() {
+            ewma.tick(count, ticks);
+        }
+    }
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ewma {
+    fn new(minutes: u64) -> Self {
+        let interval = TICK_INTERVAL.as_secs_f64();
+        Self {
+            alpha: 1.0 - (-interval / 60.0 / minutes as f64).exp(),
+            rate: 0.0,
+            initialized: false,
+        }
+    }
+
+    // count 次事件发生在第一个时间段，之后 ticks - 1 个时间段是 0 次
+    fn tick(&mut self, count: u64, ticks: u64) {
+        let instant_rate = count as f64 / TICK_INTERVAL.as_secs_f64();
+        if self.initialized {
+            self.rate += self.alpha * (instant_rate - self.rate);
+        } else {
+            self.rate = instant_rate;
+            self.initialized = true;
+        }
+
+        // 没有事件的时间段每次把速率乘以 (1 - alpha)，直接算幂，不用一次一次地循环
+        if ticks > 1 {
+            let decay = (1.0 - self.alpha).powf((ticks - 1) as f64);
+            self.rate *= decay;
+        }
+    }
+}
+
+impl Display for Meter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "count: {}", self.count())?;
+        writeln!(f, "m1_rate: {:.2}", self.one_minute_rate())?;
+        writeln!(f, "m5_rate: {:.2}", self.five_minute_rate())?;
+        writeln!(f, "m15_rate: {:.2}", self.fifteen_minute_rate())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "actual: {}, expected: {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_ewma_rates() {
+        let clock = ManualClock::new();
+        let meter = Meter::with_clock(Arc::new(clock.clone()));
+
+        meter.mark_n(300);
+        assert_eq!(meter.one_minute_rate(), 0.0);
+
+        // 第一个 5 秒：300 次，也就是每秒 60 次
+        clock.advance(TICK_INTERVAL);
+        assert_close(meter.one_minute_rate(), 60.0);
+        assert_close(meter.fifteen_minute_rate(), 60.0);
+
+        // 再过 1 分钟没有事件，1 分钟的速率衰减到 1/e
+        clock.advance(Duration::from_secs(60));
+        assert_close(meter.one_minute_rate(), 60.0 * (-1.0f64).exp());
+        assert_close(meter.five_minute_rate(), 60.0 * (-0.2f64).exp());
+        assert_close(meter.fifteen_minute_rate(), 60.0 * (-1.0f64 / 15.0).exp());
+
+        assert_eq!(meter.count(), 300);
+        assert_close(meter.mean_rate(), 300.0 / 65.0);
+    }
+
+    #[test]
+    fn test_ewma_converges_to_steady_rate() {
+        let clock = ManualClock::new();
+        let meter = Meter::with_clock(Arc::new(clock.clone()));
+
+        // 每秒 10 次，持续 30 分钟
+        for _ in 0..1_800 {
+            meter.mark_n(10);
+            clock.advance(Duration::from_secs(1));
+        }
+
+        assert!((meter.one_minute_rate() - 10.0).abs() < 1e-6);
+        assert!((meter.five_minute_rate() - 10.0).abs() < 1e-6);
+        assert!((meter.fifteen_minute_rate() - 10.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_concurrent_mark() {
+        let clock = ManualClock::new();
+        let meter = Meter::with_clock(Arc::new(clock.clone()));
+
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1_000 {
+                        meter.mark();
+                    }
+                });
+            }
+        });
+
+        clock.advance(TICK_INTERVAL);
+        assert_eq!(meter.count(), 4_000);
+        assert_close(meter.one_minute_rate(), 800.0);
+    }
+}
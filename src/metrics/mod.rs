@@ -1,5 +1,198 @@
+mod append_only_map;
 pub mod atomic_metrics;
+pub mod clock;
 pub mod concurrent_metrics;
+pub mod counter;
+pub mod exporter;
+pub mod gauge;
+pub mod guard;
+pub mod handle;
+pub mod histogram;
+pub mod json;
+pub mod labels;
+pub mod latency_histogram;
+pub mod local_metrics;
+pub mod meter;
+pub mod persist;
+pub mod prometheus;
+pub mod push;
+pub mod registry;
+pub mod reporter;
+#[cfg(feature = "http")]
+pub mod server;
+pub mod sliding_window;
+pub mod statsd;
+pub mod striped_counter;
+pub mod tracing_layer;
+mod worker;
 
 pub use atomic_metrics::*;
+pub use clock::*;
 pub use concurrent_metrics::*;
+pub use counter::*;
+pub use exporter::*;
+pub use gauge::*;
+pub use guard::*;
+pub use handle::CounterHandle;
+pub use histogram::*;
+pub use json::*;
+pub use labels::*;
+pub use latency_histogram::*;
+pub use local_metrics::*;
+pub use meter::*;
+pub use persist::*;
+pub use prometheus::PrometheusExporter;
+pub use push::*;
+pub use registry::*;
+pub use reporter::*;
+#[cfg(feature = "http")]
+pub use server::{MetricsServer, DEFAULT_READ_TIMEOUT, DEFAULT_SHUTDOWN_TIMEOUT};
+pub use sliding_window::*;
+pub use statsd::*;
+pub use striped_counter::*;
+pub use tracing_layer::*;
+
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// AtomicMetrics 和 ConcurrentMetrics 共同的接口
+/// 代码里用 M: Metrics 作为类型参数，就可以在两种实现之间切换
+/// 注意 AtomicMetrics 只能操作注册过的 key，未注册的 key 会返回错误，除非打开了 auto_register
+pub trait Metrics: Clone + Send + Sync + 'static {
+    fn inc(&self, key: impl AsRef<str>) -> Result<()> {
+        self.add(key, 1)
+    }
+
+    fn dec(&self, key: impl AsRef<str>) -> Result<()> {
+        self.add(key, -1)
+    }
+
+    fn add(&self, key: impl AsRef<str>, delta: i64) -> Result<()>;
+
+    fn set(&self, key: impl AsRef<str>, value: i64) -> Result<()>;
+
+    fn get(&self, key: impl AsRef<str>) -> Option<i64>;
+
+    /// 取出 key 的句柄，在循环中反复写同一个 key 时不需要每次都查找 key
+    fn counter(&self, key: impl AsRef<str>) -> Result<CounterHandle>;
+
+    /// 复制出当前所有 metrics 的值，不是全局一致的快照，见各个实现的说明
+    fn snapshot(&self) -> HashMap<String, i64>;
+
+    /// 按名字排序的 metric family，Reporter 和各种 exporter 的输入
+    /// key 没有种类信息，而且可以 dec / set，所以默认每个 key 都是一个没有 label 的 gauge
+    /// 除了 snapshot 之外还有其他数据的实现（比如 ConcurrentMetrics 的 timer）可以覆盖
+    fn families(&self) -> Vec<FamilySnapshot> {
+        let mut families = gauge_families(self.snapshot());
+        families.sort_by(|a, b| a.name.cmp(&b.name));
+
+        families
+    }
+}
+
+// snapshot 中的每个 key 转换成一个没有 label 的 gauge，没有排序
+pub(crate) fn gauge_families(snapshot: HashMap<String, i64>) -> Vec<FamilySnapshot> {
+    snapshot
+        .into_iter()
+        .map(|(key, value)| FamilySnapshot {
+            name: key,
+            kind: MetricKind::Gauge,
+            help: None,
+            metrics: vec![(Labels::default(), MetricValue::Gauge(value))],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    const KEYS: [&str; 2] = ["req.page.1", "req.in_flight"];
+
+    // 对 Metrics 的通用测试，两种实现跑同一份逻辑
+    fn exercise<M: Metrics>(metrics: M) -> Result<()> {
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let metrics = metrics.clone();
+                s.spawn(move || {
+                    for _ in 0..1_000 {
+                        metrics.inc(KEYS[0]).unwrap();
+                        metrics.add(KEYS[1], 3).unwrap();
+                        metrics.dec(KEYS[1]).unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(metrics.get(KEYS[0]), Some(4_000));
+        assert_eq!(metrics.get(KEYS[1]), Some(8_000));
+
+        metrics.set(KEYS[1], 42)?;
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot[KEYS[0]], 4_000);
+        assert_eq!(snapshot[KEYS[1]], 42);
+
+        let handle = metrics.counter(KEYS[0])?;
+        handle.add(2);
+        assert_eq!(metrics.get(KEYS[0]), Some(4_002));
+
+        Ok(())
+    }
+
+    const WRITERS: usize = 4;
+    const OPS: i64 = 10_000;
+    const SNAPSHOT_KEYS: [&str; 5] = ["shared", "worker.0", "worker.1", "worker.2", "worker.3"];
+
+    // 写入的同时反复 snapshot，metrics 中需要能写 SNAPSHOT_KEYS
+    fn exercise_snapshot<M: Metrics>(metrics: M) {
+        thread::scope(|s| {
+            for idx in 0..WRITERS {
+                let metrics = metrics.clone();
+                s.spawn(move || {
+                    for _ in 0..OPS {
+                        metrics.inc("shared").unwrap();
+                        metrics.inc(format!("worker.{}", idx)).unwrap();
+                    }
+                });
+            }
+
+            // 写入过程中读到的值只会单调增长，并且不会超过最终值
+            let mut last = 0;
+            for _ in 0..100 {
+                let value = metrics.snapshot().get("shared").copied().unwrap_or(0);
+                assert!(value >= last);
+                assert!(value <= OPS * WRITERS as i64);
+                last = value;
+            }
+        });
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot["shared"], OPS * WRITERS as i64);
+        for idx in 0..WRITERS {
+            assert_eq!(snapshot[&format!("worker.{}", idx)], OPS);
+        }
+    }
+
+    #[test]
+    fn test_atomic_metrics_as_metrics() -> Result<()> {
+        let metrics = AtomicMetrics::new(&KEYS);
+        exercise(metrics.clone())?;
+        exercise(AtomicMetrics::with_storage(&KEYS, AtomicStorage::Striped))?;
+
+        assert!(Metrics::inc(&metrics, "unknown").is_err());
+        assert!(Metrics::set(&metrics, "unknown", 1).is_err());
+
+        exercise_snapshot(AtomicMetrics::new(&SNAPSHOT_KEYS));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_metrics_as_metrics() -> Result<()> {
+        exercise(ConcurrentMetrics::new())?;
+        exercise_snapshot(ConcurrentMetrics::new());
+
+        Ok(())
+    }
+}
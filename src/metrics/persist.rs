@@ -0,0 +1,313 @@
+use crate::{MetricKind, Metrics, Report, ReportSink, OVERFLOW_KEY};
+use anyhow::{anyhow, Result};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// 快照文件格式的版本，格式不兼容地改变时加 1
+/// 版本 1：{"version": 1, "saved_at": <unix 秒>, "metrics": {"key": value, ...}}
+pub const SNAPSHOT_VERSION: u64 = 1;
+
+/// 把 metrics 当前的值写入文件，用于进程重启之后恢复
+/// 只支持 Metrics（AtomicMetrics / ConcurrentMetrics）的 key -> i64，MetricsRegistry 的 label、
+/// float gauge 和 histogram 都不在快照中，ConcurrentMetrics 的 timer 也不在
+///
+/// 先写到同一个目录下的临时文件，fsync 之后再 rename 覆盖目标文件，最后 fsync 所在的目录
+/// rename 是原子的，所以即使写到一半进程崩溃，目标文件也只会是旧的完整快照或者新的完整快照
+/// 目录 fsync 之后 rename 本身也落盘了，掉电之后不会回到旧的快照（目录的 fsync 只在 unix 上做）
+/// 同一个文件同一时间只应该有一个写入者
+pub fn save_snapshot<M: Metrics>(metrics: &M, path: impl AsRef<Path>) -> Result<()> {
+    write_snapshot(&metrics.snapshot(), path.as_ref())
+}
+
+/// 读取快照文件，版本不是 SNAPSHOT_VERSION 时返回错误
+pub fn load_snapshot(path: impl AsRef<Path>) -> Result<HashMap<String, i64>> {
+    let content = fs::read_to_string(path)?;
+    let value = serde_json::from_str::<Value>(&content)?;
+
+    let version = value.get("version").and_then(Value::as_u64);
+    if version != Some(SNAPSHOT_VERSION) {
+        return Err(anyhow!("unsupported snapshot version: {:?}", version));
+    }
+
+    let metrics = value
+        .get("metrics")
+        .and_then(Value::as_object)
+        .ok_or_else(|| anyhow!("snapshot has no metrics"))?;
+    metrics
+        .iter()
+        .map(|(key, value)| {
+            let value = value
+                .as_i64()
+                .ok_or_else(|| anyhow!("invalid value for key {}", key))?;
+            Ok((key.clone(), value))
+        })
+        .collect()
+}
+
+/// 启动时从快照恢复：对快照中的每个 key 调用 set，返回恢复了几个 key
+/// 文件不存在时（比如第一次启动）什么都不做，返回 0
+/// set 失败的 key（比如 AtomicMetrics 中没有注册的 key）会被跳过并打印警告，不影响其他 key
+/// OVERFLOW_KEY 不会恢复，它只是超过上限的 key 的合计，不是一个真正的 key
+pub fn restore_snapshot<M: Metrics>(metrics: &M, path: impl AsRef<Path>) -> Result<usize> {
+    let snapshot = match load_snapshot(&path) {
+        Ok(snapshot) => snapshot,
+        Err(e) if is_not_found(&e) => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    let mut restored = 0;
+    for (key, value) in snapshot {
+        if key == OVERFLOW_KEY {
+            continue;
+        }
+
+        match metrics.set(&key, value) {
+            Ok(()) => restored += 1,
+            Err(e) => warn!("restore metric {} error: {}", key, e),
+        }
+    }
+
+    Ok(restored)
+}
+
+/// 配合 Reporter 定期保存快照，每次汇报时把 Report 中的值写入文件
+/// Reporter 停止时会再汇报一次，所以正常退出时保存的是最后的值
+/// 和 save_snapshot 一样只支持 Metrics 数据源（AtomicMetrics / ConcurrentMetrics）
+/// 没有 label 的 histogram（比如 ConcurrentMetrics 的 timer）不保存，直接跳过
+/// Report 中有 counter、float gauge 或者带 label 的 metric 时（数据源是 MetricsRegistry），report 返回错误，不写文件
+#[derive(Debug, Clone)]
+pub struct SnapshotSink {
+    path: PathBuf,
+}
+
+impl SnapshotSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl ReportSink for SnapshotSink {
+    fn report(&mut self, report: &Report) -> Result<()> {
+        // Metrics 数据源的每个 key 都是一个没有 label 的 gauge，timer 是没有 label 的 histogram，见 Metrics::families
+        let unsupported = report.families.iter().find(|family| {
+            !matches!(family.kind, MetricKind::Gauge | MetricKind::Histogram)
+                || family.metrics.iter().any(|(labels, _)| !labels.is_empty())
+        });
+        if let Some(family) = unsupported {
+            return Err(anyhow!(
+                "snapshot sink only supports Metrics sources, got {} {}",
+                family.kind,
+                family.name
+            ));
+        }
+
+        let values = report
+            .entries
+            .iter()
+            .map(|entry| (entry.key.clone(), entry.value))
+            .collect();
+        write_snapshot(&values, &self.path)
+    }
+}
+
+fn write_snapshot(values: &HashMap<String, i64>, path: &Path) -> Result<()> {
+    // 按 key 排序，同样的值写出来的文件相同，方便比较
+    let mut keys = values.keys().collect::<Vec<_>>();
+    keys.sort();
+    let metrics = keys
+        .into_iter()
+        .map(|key| (key.clone(), Value::from(values[key])))
+        .collect::<Map<_, _>>();
+
+    let saved_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let content = json!({
+        "version": SNAPSHOT_VERSION,
+        "saved_at": saved_at,
+        "metrics": metrics,
+    });
+
+    let tmp_path = tmp_path(path)?;
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(serde_json::to_string_pretty(&content)?.as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e.into());
+    }
+    sync_dir(path)?;
+
+    Ok(())
+}
+
+// rename 修改的是目录，要 fsync 目录才能保证掉电之后 rename 仍然生效
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        // 相对路径 "metrics.json" 的 parent 是 ""
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()?;
+
+    Ok(())
+}
+
+// windows 上不能像 unix 一样打开目录再 fsync
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+// 和目标文件在同一个目录下，rename 才不会跨文件系统
+fn tmp_path(path: &Path) -> Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("invalid snapshot path: {}", path.display()))?;
+
+    let mut tmp_name = file_name.to_os_string();
+    tmp_name.push(".tmp");
+    Ok(path.with_file_name(tmp_name))
+}
+
+fn is_not_found(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == ErrorKind::NotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AtomicMetrics, ConcurrentMetrics, MetricsRegistry, Reporter};
+    use std::time::Duration;
+
+    // 每个测试用自己的目录，测试结束时删除
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Result<Self> {
+            let dir = std::env::temp_dir().join(format!(
+                "rs-concurrency-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir)?;
+            Ok(Self(dir))
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let dir = TempDir::new("round-trip")?;
+        let path = dir.0.join("metrics.json");
+
+        let metrics = ConcurrentMetrics::new();
+        for page in 1..=10 {
+            metrics.add(format!("req.page.{}", page), page * 100)?;
+        }
+        metrics.set("in_flight", -3)?;
+        save_snapshot(&metrics, &path)?;
+        // 覆盖已有的文件，不留下临时文件
+        save_snapshot(&metrics, &path)?;
+        assert_eq!(fs::read_dir(&dir.0)?.count(), 1);
+        // 相对路径的目录是当前目录
+        sync_dir(Path::new("metrics.json"))?;
+
+        let restored = ConcurrentMetrics::new();
+        assert_eq!(restore_snapshot(&restored, &path)?, 11);
+        assert_eq!(restored.snapshot(), metrics.snapshot());
+
+        // AtomicMetrics 中没有注册的 key 被跳过
+        let atomic = AtomicMetrics::new(&["req.page.1", "in_flight"]);
+        assert_eq!(restore_snapshot(&atomic, &path)?, 2);
+        assert_eq!(atomic.get("req.page.1"), Some(100));
+        assert_eq!(atomic.get("in_flight"), Some(-3));
+
+        // 超过上限的合计不会被当成一个 key 恢复
+        let limited = ConcurrentMetrics::new().with_max_keys(0);
+        limited.inc("dropped")?;
+        save_snapshot(&limited, &path)?;
+        assert_eq!(load_snapshot(&path)?[OVERFLOW_KEY], 1);
+        let restored = ConcurrentMetrics::new();
+        assert_eq!(restore_snapshot(&restored, &path)?, 0);
+        assert!(restored.snapshot().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_file_and_bad_version() -> Result<()> {
+        let dir = TempDir::new("bad-version")?;
+        let path = dir.0.join("metrics.json");
+
+        assert_eq!(restore_snapshot(&ConcurrentMetrics::new(), &path)?, 0);
+
+        fs::write(&path, r#"{"version": 2, "metrics": {"a": 1}}"#)?;
+        assert!(load_snapshot(&path).is_err());
+        assert!(restore_snapshot(&ConcurrentMetrics::new(), &path).is_err());
+
+        fs::write(&path, r#"{"version": 1, "metrics": {"a": "x"}}"#)?;
+        assert!(load_snapshot(&path).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_sink() -> Result<()> {
+        let dir = TempDir::new("sink")?;
+        let path = dir.0.join("metrics.json");
+
+        let metrics = ConcurrentMetrics::new();
+        let reporter = Reporter::spawn(
+            metrics.clone(),
+            Duration::from_millis(10),
+            SnapshotSink::new(&path),
+        );
+        metrics.add("req", 42)?;
+        // timer 不保存，但也不影响其他 key 的保存
+        metrics.histogram("db.query").record(1_000);
+        reporter.stop()?;
+
+        assert_eq!(
+            load_snapshot(&path)?,
+            HashMap::from([("req".to_string(), 42)])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_sink_rejects_registry() -> Result<()> {
+        let dir = TempDir::new("sink-registry")?;
+        let path = dir.0.join("metrics.json");
+
+        let registry = MetricsRegistry::new();
+        registry.counter_with_labels("req", &[("page", "1")])?.inc();
+        let report = Report {
+            elapsed: Duration::from_secs(1),
+            entries: Vec::new(),
+            families: registry.collect(),
+        };
+
+        // 带 label 的 counter 没法用 restore_snapshot 恢复，不写入文件
+        assert!(SnapshotSink::new(&path).report(&report).is_err());
+        assert!(!path.exists());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,299 @@
+use crate::{FamilySnapshot, Labels, MetricKind, MetricValue, MetricsExporter, MetricsRegistry};
+use std::collections::HashSet;
+use std::fmt::Write;
+use tracing::warn;
+
+/// 把 MetricsRegistry 输出成 Prometheus 的文本格式
+/// <https://prometheus.io/docs/instrumenting/exposition_formats/>
+///
+/// - 每个 metric family 输出 # HELP 和 # TYPE，没有设置说明时 HELP 用原来的名字
+/// - 名字中 Prometheus 不允许的字符（比如 `.`）替换成 `_`
+/// - 替换之后和前面的 family 重名的（比如 `req.total` 和 `req_total`，或者和 histogram 的 `_sum` 等重名）
+///   不会输出，只打印警告；重复的 # TYPE 会让 Prometheus 拒绝整个抓取结果
+/// - histogram 输出累加的 `_bucket{le="..."}`，以及 `_sum` 和 `_count`
+/// - family 按名字排序，同一个 family 内按 label 排序，输出是稳定的，可以和固定的文本比较
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrometheusExporter;
+
+impl PrometheusExporter {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn render(&self, registry: &MetricsRegistry) -> String {
+        self.export_registry(registry)
+    }
+}
+
+impl MetricsExporter for PrometheusExporter {
+    fn export(&self, families: &[FamilySnapshot]) -> String {
+        let mut out = String::new();
+        let mut names = HashSet::new();
+        for family in families {
+            let reserved = output_names(family);
+            if let Some(name) = reserved.iter().find(|name| names.contains(*name)) {
+                warn!(
+                    "metric {} skipped: name {} is already used by another metric",
+                    family.name, name
+                );
+                continue;
+            }
+
+            names.extend(reserved);
+            render_family(&mut out, family);
+        }
+
+        out
+    }
+}
+
+// family 输出时会用到的所有名字，histogram 还包括 _bucket / _sum / _count
+fn output_names(family: &FamilySnapshot) -> Vec<String> {
+    let name = sanitize_name(&family.name);
+    match family.kind {
+        MetricKind::Histogram => ["_bucket", "_sum", "_count"]
+            .iter()
+            .map(|suffix| format!("{}{}", name, suffix))
+            .chain([name.clone()])
+            .collect(),
+        _ => vec![name],
+    }
+}
+
+fn render_family(out: &mut String, family: &FamilySnapshot) {
+    let name = sanitize_name(&family.name);
+    let help = family.help.as_deref().unwrap_or(&family.name);
+    let kind = match family.kind {
+        MetricKind::Counter => "counter",
+        MetricKind::Gauge | MetricKind::FloatGauge => "gauge",
+        MetricKind::Histogram => "histogram",
+    };
+
+    // 写入 String 不会失败，忽略 fmt::Result
+    let _ = writeln!(out, "# HELP {} {}", name, escape_help(help));
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+
+    for (labels, value) in &family.metrics {
+        match value {
+            MetricValue::Counter(v) => write_sample(out, &name, labels, None, &v.to_string()),
+            MetricValue::Gauge(v) => write_sample(out, &name, labels, None, &v.to_string()),
+            MetricValue::FloatGauge(v) => write_sample(out, &name, labels, None, &format_float(*v)),
+            MetricValue::Histogram(h) => {
+                let bucket_name = format!("{}_bucket", name);
+
+                // Prometheus 的 bucket 是累加的：le="x" 表示 <= x 的总次数
+                let mut cumulative = 0;
+                for (bound, count) in h.bounds.iter().zip(&h.buckets) {
+                    cumulative += count;
+                    let le = format_float(*bound);
+                    write_sample(
+                        out,
+                        &bucket_name,
+                        labels,
+                        Some(&le),
+                        &cumulative.to_string(),
+                    );
+                }
+                write_sample(
+                    out,
+                    &bucket_name,
+                    labels,
+                    Some("+Inf"),
+                    &h.count.to_string(),
+                );
+
+                write_sample(
+                    out,
+                    &format!("{}_sum", name),
+                    labels,
+                    None,
+                    &format_float(h.sum),
+                );
+                write_sample(
+                    out,
+                    &format!("{}_count", name),
+                    labels,
+                    None,
+                    &h.count.to_string(),
+                );
+            }
+        }
+    }
+}
+
+// name{label="value",le="x"} value
+fn write_sample(out: &mut String, name: &str, labels: &Labels, le: Option<&str>, value: &str) {
+    out.push_str(name);
+
+    let mut pairs = labels
+        .iter()
+        .map(|(k, v)| (sanitize_label_name(k), escape_label_value(v)))
+        .collect::<Vec<_>>();
+    if let Some(le) = le {
+        pairs.push(("le".to_string(), le.to_string()));
+    }
+
+    if !pairs.is_empty() {
+        out.push('{');
+        for (i, (k, v)) in pairs.iter().enumerate() {
+            if i != 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{}=\"{}\"", k, v);
+        }
+        out.push('}');
+    }
+
+    let _ = writeln!(out, " {}", value);
+}
+
+/// metric 名字只能包含 [a-zA-Z0-9_:]，并且不能以数字开头
+pub fn sanitize_name(name: &str) -> String {
+    sanitize(name, |c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// label 名字只能包含 [a-zA-Z0-9_]，并且不能以数字开头
+pub fn sanitize_label_name(name: &str) -> String {
+    sanitize(name, |c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn sanitize(name: &str, valid: impl Fn(char) -> bool) -> String {
+    let mut result = name
+        .chars()
+        .map(|c| if valid(c) { c } else { '_' })
+        .collect::<String>();
+
+    if result.is_empty() || result.starts_with(|c: char| c.is_ascii_digit()) {
+        result.insert(0, '_');
+    }
+
+    result
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', r"\\").replace('\n', r"\n")
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+// Rust 输出的是 inf / NaN，Prometheus 需要 +Inf / -Inf / NaN
+fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn test_render_golden() -> Result<()> {
+        let registry = MetricsRegistry::new();
+        registry.describe("req.total", "Total number of requests.");
+
+        registry
+            .counter_with_labels("req.total", &[("page", "2"), ("method", "GET")])?
+            .add(5);
+        registry
+            .counter_with_labels("req.total", &[("page", "1"), ("method", "GET")])?
+            .add(3);
+        registry.gauge("req.in_flight")?.set(-2);
+        registry.float_gauge("cpu.usage")?.set(0.25);
+
+        let latency =
+            registry.histogram_with_labels("req.latency", &[("method", "GET")], &[0.1, 1.0])?;
+        latency.record(0.05);
+        latency.record(0.5);
+        latency.record(2.0);
+
+        let expected = r#"# HELP cpu_usage cpu.usage
+# TYPE cpu_usage gauge
+cpu_usage 0.25
+# HELP req_in_flight req.in_flight
+# TYPE req_in_flight gauge
+req_in_flight -2
+# HELP req_latency req.latency
+# TYPE req_latency histogram
+req_latency_bucket{method="GET",le="0.1"} 1
+req_latency_bucket{method="GET",le="1"} 2
+req_latency_bucket{method="GET",le="+Inf"} 3
+req_latency_sum{method="GET"} 2.55
+req_latency_count{method="GET"} 3
+# HELP req_total Total number of requests.
+# TYPE req_total counter
+req_total{method="GET",page="1"} 3
+req_total{method="GET",page="2"} 5
+"#;
+        assert_eq!(PrometheusExporter::new().render(&registry), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sanitize_and_escape() -> Result<()> {
+        assert_eq!(sanitize_name("http.req-total"), "http_req_total");
+        assert_eq!(sanitize_name("a:b_c"), "a:b_c");
+        assert_eq!(sanitize_name("1xx"), "_1xx");
+        assert_eq!(sanitize_label_name("a:b"), "a_b");
+
+        let registry = MetricsRegistry::new();
+        registry.describe("errors", "line one\nline \\two");
+        registry
+            .counter_with_labels("errors", &[("msg", "say \"hi\"\n"), ("1st.label", "x")])?
+            .inc();
+
+        let expected = r#"# HELP errors line one\nline \\two
+# TYPE errors counter
+errors{_1st_label="x",msg="say \"hi\"\n"} 1
+"#;
+        assert_eq!(PrometheusExporter::new().render(&registry), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_name_collision() -> Result<()> {
+        let registry = MetricsRegistry::new();
+        registry.counter("req.total")?.add(1);
+        registry.counter("req_total")?.add(2);
+        registry.histogram("latency", &[1.0])?.record(0.5);
+        registry.gauge("latency.sum")?.set(3);
+
+        // 排在前面的 family 保留，后面重名的不输出
+        let expected = r#"# HELP latency latency
+# TYPE latency histogram
+latency_bucket{le="1"} 1
+latency_bucket{le="+Inf"} 1
+latency_sum 0.5
+latency_count 1
+# HELP req_total req.total
+# TYPE req_total counter
+req_total 1
+"#;
+        assert_eq!(PrometheusExporter::new().render(&registry), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_float() {
+        assert_eq!(format_float(1.0), "1");
+        assert_eq!(format_float(0.005), "0.005");
+        assert_eq!(format_float(f64::INFINITY), "+Inf");
+        assert_eq!(format_float(f64::NEG_INFINITY), "-Inf");
+        assert_eq!(format_float(f64::NAN), "NaN");
+    }
+}
@@ -0,0 +1,171 @@
+use super::worker::BackgroundWorker;
+use crate::{MetricsExporter, MetricsRegistry};
+use anyhow::{anyhow, Result};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+use tracing::warn;
+
+// 一个 UDP 包最多放这么多字节，以太网 MTU 1500 减去 IP 和 UDP 头，再留一些余量
+const MAX_DATAGRAM_SIZE: usize = 1432;
+
+/// 在后台线程中定期对 registry 取快照，用 exporter 编码后通过 UDP 发送出去
+/// 比如配合 StatsdExporter 推送给 StatsD agent
+///
+/// 输出按行拆分成多个 UDP 包，每个包不超过 MAX_DATAGRAM_SIZE，一行不会被拆到两个包中
+/// StatsD 负数 gauge 的 `name:0|g` 和 `name:-N|g` 两行总是在同一个包中
+/// 调用 stop 或者 drop 时会停止后台线程，停止之前会再推送一次，保证最后的数据不会丢
+pub struct PushReporter {
+    worker: BackgroundWorker,
+}
+
+impl PushReporter {
+    pub fn start(
+        registry: MetricsRegistry,
+        exporter: impl MetricsExporter + 'static,
+        target: impl ToSocketAddrs,
+        interval: Duration,
+    ) -> Result<Self> {
+        let target = target
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("no address to push metrics to"))?;
+        let bind_addr: SocketAddr = if target.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(bind_addr)?;
+        socket.connect(target)?;
+
+        let worker = BackgroundWorker::spawn("push reporter", interval, move || {
+            let payload = exporter.export(&registry.collect());
+            if let Err(e) = send_payload(&socket, &payload) {
+                warn!("push metrics to {} error: {}", target, e);
+            }
+        });
+
+        Ok(Self { worker })
+    }
+
+    /// 停止后台线程，等它推送完最后一次再返回
+    pub fn stop(mut self) -> Result<()> {
+        self.worker.stop()
+    }
+}
+
+fn send_payload(socket: &UdpSocket, payload: &str) -> Result<()> {
+    for datagram in split_lines(payload, MAX_DATAGRAM_SIZE) {
+        socket.send(datagram.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+// 按行把 payload 分成若干块，每块不超过 max_size，超过 max_size 的单行自己一块
+// 负数 gauge 的两行如果被拆到两个包中，丢包或者乱序时服务端会看到错误的值，所以当成一行处理
+fn split_lines(payload: &str, max_size: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut end = 0;
+    let mut lines = payload.split_inclusive('\n').peekable();
+
+    while let Some(line) = lines.next() {
+        let mut len = line.len();
+        if let Some(next) = lines.next_if(|next| is_negative_gauge(line, next)) {
+            len += next.len();
+        }
+
+        if end > start && end - start + len > max_size {
+            chunks.push(payload[start..end].trim_end_matches('\n'));
+            start = end;
+        }
+        end += len;
+    }
+    if end > start {
+        chunks.push(payload[start..end].trim_end_matches('\n'));
+    }
+
+    chunks
+}
+
+// line 是 `name:0|g...`，next 是同一个 name 的 `name:-N|g...`，见 StatsdExporter
+fn is_negative_gauge(line: &str, next: &str) -> bool {
+    let Some((name, value)) = line.split_once(':') else {
+        return false;
+    };
+
+    value.starts_with("0|g")
+        && next
+            .strip_prefix(name)
+            .is_some_and(|rest| rest.starts_with(":-"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StatsdExporter;
+
+    #[test]
+    fn test_split_lines() {
+        assert!(split_lines("", 10).is_empty());
+        assert_eq!(split_lines("a:1|c\nb:2|c\n", 100), vec!["a:1|c\nb:2|c"]);
+        assert_eq!(split_lines("a:1|c\nb:2|c\n", 8), vec!["a:1|c", "b:2|c"]);
+        // 超过上限的单行不会被截断
+        assert_eq!(
+            split_lines("long_line:1|c\nb:2|c\n", 8),
+            vec!["long_line:1|c", "b:2|c"]
+        );
+
+        // 负数 gauge 的两行不会被拆开
+        let payload = "a:1|c\ng:0|g\ng:-2|g\nb:2|c\n";
+        assert_eq!(
+            split_lines(payload, 14),
+            vec!["a:1|c", "g:0|g\ng:-2|g", "b:2|c"]
+        );
+        assert_eq!(
+            split_lines(payload, 20),
+            vec!["a:1|c\ng:0|g\ng:-2|g", "b:2|c"]
+        );
+        // 名字不同的两行照常拆分
+        assert_eq!(split_lines("g:0|g\nh:-2|g\n", 6), vec!["g:0|g", "h:-2|g"]);
+    }
+
+    #[test]
+    fn test_push_reporter() -> Result<()> {
+        let receiver = UdpSocket::bind("127.0.0.1:0")?;
+        receiver.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+        let registry = MetricsRegistry::new();
+        registry.counter("req")?.add(3);
+        registry.gauge("in_flight")?.set(2);
+
+        let reporter = PushReporter::start(
+            registry.clone(),
+            StatsdExporter::new(""),
+            receiver.local_addr()?,
+            Duration::from_millis(20),
+        )?;
+
+        let mut buf = [0; MAX_DATAGRAM_SIZE];
+        let n = receiver.recv(&mut buf)?;
+        assert_eq!(&buf[..n], b"in_flight:2|g\nreq:3|c");
+
+        // 停止时会推送最后一次，counter 只带上增量，所有包中的增量加起来就是 counter 的值
+        registry.counter("req")?.add(2);
+        reporter.stop()?;
+        let mut total = 3;
+        receiver.set_read_timeout(Some(Duration::from_millis(200)))?;
+        while let Ok(n) = receiver.recv(&mut buf) {
+            for line in String::from_utf8_lossy(&buf[..n]).lines() {
+                if let Some(value) = line.strip_prefix("req:") {
+                    total += value.trim_end_matches("|c").parse::<u64>()?;
+                } else {
+                    assert_eq!(line, "in_flight:2|g");
+                }
+            }
+        }
+        assert_eq!(total, 5);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,881 @@
+use crate::{Counter, FloatGauge, Gauge, Histogram, HistogramSnapshot, Labels, MetricKey};
+use anyhow::{anyhow, Result};
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+/// 注册在 MetricsRegistry 中的一个 metric
+#[derive(Debug, Clone)]
+pub enum Metric {
+    Counter(Counter),
+    Gauge(Gauge),
+    FloatGauge(FloatGauge),
+    Histogram(Histogram),
+}
+
+/// metric 的种类，同一个名字下所有 label 组合的种类必须相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+    FloatGauge,
+    Histogram,
+}
+
+/// snapshot 中一个 metric 的值
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetricValue {
+    Counter(u64),
+    Gauge(i64),
+    FloatGauge(f64),
+    Histogram(HistogramSnapshot),
+}
+
+/// 合并两个 gauge 时取哪个值，counter 总是相加，histogram 总是按 bucket 相加
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GaugeMerge {
+    /// 相加，比如每个 worker 正在处理的请求数，合起来就是总数
+    #[default]
+    Sum,
+    Max,
+    Min,
+    /// 用后合并进来的值覆盖，比如每个 worker 上报的同一个配置值
+    Last,
+}
+
+/// 按名字和 label 管理不同种类的 metric，和 ConcurrentMetrics 一样用 DashMap 存储
+/// 不同的是 DashMap 里存的是 metric 的句柄（内部是 Arc 的原子变量），而不是值本身
+/// 拿到句柄之后的读写都是原子操作，不再经过 DashMap 的锁，也不需要再拼接或者分配 key
+///
+/// 两层结构：名字 -> MetricFamily，MetricFamily 里是 label 组合 -> metric
+/// clone 是对 Arc 进行 clone
+#[derive(Debug, Clone, Default)]
+pub struct MetricsRegistry {
+    data: Arc<DashMap<String, MetricFamily>>,
+    // metric 的说明，可以在 metric 注册之前设置
+    help: Arc<DashMap<String, String>>,
+}
+
+/// 一个名字下所有 metric 的值，给 exporter 使用
+/// metrics 按 label 排序，输出的顺序是稳定的
+#[derive(Debug, Clone, PartialEq)]
+pub struct FamilySnapshot {
+    pub name: String,
+    pub kind: MetricKind,
+    pub help: Option<String>,
+    pub metrics: Vec<(Labels, MetricValue)>,
+}
+
+// 同一个名字下的所有 metric
+#[derive(Debug, Clone)]
+struct MetricFamily {
+    kind: MetricKind,
+    metrics: Arc<DashMap<Labels, Metric>>,
+}
+
+impl Metric {
+    pub fn kind(&self) -> MetricKind {
+        match self {
+            Metric::Counter(_) => MetricKind::Counter,
+            Metric::Gauge(_) => MetricKind::Gauge,
+            Metric::FloatGauge(_) => MetricKind::FloatGauge,
+            Metric::Histogram(_) => MetricKind::Histogram,
+        }
+    }
+
+    pub fn value(&self) -> MetricValue {
+        match self {
+            Metric::Counter(c) => MetricValue::Counter(c.get()),
+            Metric::Gauge(g) => MetricValue::Gauge(g.get()),
+            Metric::FloatGauge(g) => MetricValue::FloatGauge(g.get()),
+            Metric::Histogram(h) => MetricValue::Histogram(h.snapshot()),
+        }
+    }
+}
+
+impl MetricValue {
+    /// counter 和 gauge 的值，histogram 没有单个的值，返回 None
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            MetricValue::Counter(v) => Some(*v as f64),
+            MetricValue::Gauge(v) => Some(*v as f64),
+            MetricValue::FloatGauge(v) => Some(*v),
+            MetricValue::Histogram(_) => None,
+        }
+    }
+
+    pub fn kind(&self) -> MetricKind {
+        match self {
+            MetricValue::Counter(_) => MetricKind::Counter,
+            MetricValue::Gauge(_) => MetricKind::Gauge,
+            MetricValue::FloatGauge(_) => MetricKind::FloatGauge,
+            MetricValue::Histogram(_) => MetricKind::Histogram,
+        }
+    }
+
+    /// 把 other 合并进来：counter 相加，gauge 按 gauge 策略，histogram 按 bucket 相加
+    /// 种类不同，或者 histogram 的 bounds 不同时返回错误
+    pub fn merge(&mut self, other: &MetricValue, gauge: GaugeMerge) -> Result<()> {
+        match (self, other) {
+            (MetricValue::Counter(a), MetricValue::Counter(b)) => *a += b,
+            (MetricValue::Gauge(a), MetricValue::Gauge(b)) => *a = gauge.merge_i64(*a, *b),
+            (MetricValue::FloatGauge(a), MetricValue::FloatGauge(b)) => {
+                *a = gauge.merge_f64(*a, *b)
+            }
+            (MetricValue::Histogram(a), MetricValue::Histogram(b)) => a.merge(b)?,
+            (a, b) => return Err(kind_mismatch_value(a.kind(), b.kind())),
+        }
+
+        Ok(())
+    }
+
+    /// 从 earlier 到现在的变化：counter 和 histogram 是新增的部分，比 earlier 小时当作被重置过，返回现在的值
+    /// gauge 表示的是当前的状态，两次之间的差没有意义，返回现在的值
+    pub fn diff(&self, earlier: &MetricValue) -> Result<MetricValue> {
+        let value = match (self, earlier) {
+            (MetricValue::Counter(a), MetricValue::Counter(b)) => {
+                MetricValue::Counter(a.checked_sub(*b).unwrap_or(*a))
+            }
+            (MetricValue::Gauge(_), MetricValue::Gauge(_))
+            | (MetricValue::FloatGauge(_), MetricValue::FloatGauge(_)) => self.clone(),
+            (MetricValue::Histogram(a), MetricValue::Histogram(b)) => {
+                MetricValue::Histogram(a.diff(b)?)
+            }
+            (a, b) => return Err(kind_mismatch_value(a.kind(), b.kind())),
+        };
+
+        Ok(value)
+    }
+}
+
+impl GaugeMerge {
+    fn merge_i64(self, a: i64, b: i64) -> i64 {
+        match self {
+            GaugeMerge::Sum => a + b,
+            GaugeMerge::Max => a.max(b),
+            GaugeMerge::Min => a.min(b),
+            GaugeMerge::Last => b,
+        }
+    }
+
+    fn merge_f64(self, a: f64, b: f64) -> f64 {
+        match self {
+            GaugeMerge::Sum => a + b,
+            GaugeMerge::Max => a.max(b),
+            GaugeMerge::Min => a.min(b),
+            GaugeMerge::Last => b,
+        }
+    }
+}
+
+impl FamilySnapshot {
+    /// 把同名的 family 合并进来，label 组合相同的 metric 按 MetricValue::merge 合并，其他的直接加入
+    /// 名字或者种类不同时返回错误
+    pub fn merge(&mut self, other: &FamilySnapshot, gauge: GaugeMerge) -> Result<()> {
+        self.check_same(other)?;
+
+        for (labels, value) in &other.metrics {
+            // metrics 按 label 排序，二分查找，插入时保持有序
+            match self.metrics.binary_search_by(|(l, _)| l.cmp(labels)) {
+                Ok(idx) => self.metrics[idx].1.merge(value, gauge)?,
+                Err(idx) => self.metrics.insert(idx, (labels.clone(), value.clone())),
+            }
+        }
+        if self.help.is_none() {
+            self.help = other.help.clone();
+        }
+
+        Ok(())
+    }
+
+    /// 每个 metric 从 earlier 到现在的变化，见 MetricValue::diff，earlier 中没有的 metric 返回现在的值
+    pub fn diff(&self, earlier: &FamilySnapshot) -> Result<FamilySnapshot> {
+        self.check_same(earlier)?;
+
+        let metrics = self
+            .metrics
+            .iter()
+            .map(|(labels, value)| {
+                let value = match earlier.metrics.binary_search_by(|(l, _)| l.cmp(labels)) {
+                    Ok(idx) => value.diff(&earlier.metrics[idx].1)?,
+                    Err(_) => value.clone(),
+                };
+                Ok((labels.clone(), value))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(FamilySnapshot {
+            name: self.name.clone(),
+            kind: self.kind,
+            help: self.help.clone(),
+            metrics,
+        })
+    }
+
+    fn check_same(&self, other: &FamilySnapshot) -> Result<()> {
+        if self.name != other.name {
+            return Err(anyhow!("metric family {} is not {}", other.name, self.name));
+        }
+        if self.kind != other.kind {
+            return Err(kind_mismatch(&self.name, other.kind, self.kind));
+        }
+
+        Ok(())
+    }
+}
+
+/// 把 other 中的 family 合并到 families 中，同名的按 FamilySnapshot::merge 合并，其他的直接加入
+/// families 需要按名字排序（collect 的结果就是），合并之后仍然有序
+/// 比如每个 worker 的 registry collect 一次，依次合并，得到所有 worker 合起来的结果
+pub fn merge_families(
+    families: &mut Vec<FamilySnapshot>,
+    other: &[FamilySnapshot],
+    gauge: GaugeMerge,
+) -> Result<()> {
+    for family in other {
+        match families.binary_search_by(|f| f.name.cmp(&family.name)) {
+            Ok(idx) => families[idx].merge(family, gauge)?,
+            Err(idx) => families.insert(idx, family.clone()),
+        }
+    }
+
+    Ok(())
+}
+
+/// 每个 family 从 earlier 到 current 的变化，见 FamilySnapshot::diff，两者都需要按名字排序
+/// earlier 中没有的 family 返回现在的值
+pub fn diff_families(
+    current: &[FamilySnapshot],
+    earlier: &[FamilySnapshot],
+) -> Result<Vec<FamilySnapshot>> {
+    current
+        .iter()
+        .map(
+            |family| match earlier.binary_search_by(|f| f.name.cmp(&family.name)) {
+                Ok(idx) => family.diff(&earlier[idx]),
+                Err(_) => Ok(family.clone()),
+            },
+        )
+        .collect()
+}
+
+impl MetricsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 获取名为 name 的 counter，不存在时创建
+    /// 同一个名字已经注册成其他种类时返回错误
+    pub fn counter(&self, name: impl AsRef<str>) -> Result<Counter> {
+        self.counter_with_labels(name, &[])
+    }
+
+    /// 获取带 label 的 counter，比如 counter_with_labels("req", &[("page", "1")])
+    /// 返回的句柄可以保存下来反复使用，之后的 inc 只是一次原子加法
+    pub fn counter_with_labels(
+        &self,
+        name: impl AsRef<str>,
+        labels: &[(&str, &str)],
+    ) -> Result<Counter> {
+        let name = name.as_ref();
+        match self.get_or_insert_with(name, labels, MetricKind::Counter, || {
+            Ok(Metric::Counter(Counter::new()))
+        })? {
+            Metric::Counter(c) => Ok(c),
+            other => Err(kind_mismatch(name, other.kind(), MetricKind::Counter)),
+        }
+    }
+
+    pub fn gauge(&self, name: impl AsRef<str>) -> Result<Gauge> {
+        self.gauge_with_labels(name, &[])
+    }
+
+    pub fn gauge_with_labels(
+        &self,
+        name: impl AsRef<str>,
+        labels: &[(&str, &str)],
+    ) -> Result<Gauge> {
+        let name = name.as_ref();
+        match self.get_or_insert_with(name, labels, MetricKind::Gauge, || {
+            Ok(Metric::Gauge(Gauge::new()))
+        })? {
+            Metric::Gauge(g) => Ok(g),
+            other => Err(kind_mismatch(name, other.kind(), MetricKind::Gauge)),
+        }
+    }
+
+    pub fn float_gauge(&self, name: impl AsRef<str>) -> Result<FloatGauge> {
+        self.float_gauge_with_labels(name, &[])
+    }
+
+    pub fn float_gauge_with_labels(
+        &self,
+        name: impl AsRef<str>,
+        labels: &[(&str, &str)],
+    ) -> Result<FloatGauge> {
+        let name = name.as_ref();
+        match self.get_or_insert_with(name, labels, MetricKind::FloatGauge, || {
+            Ok(Metric::FloatGauge(FloatGauge::new()))
+        })? {
+            Metric::FloatGauge(g) => Ok(g),
+            other => Err(kind_mismatch(name, other.kind(), MetricKind::FloatGauge)),
+        }
+    }
+
+    /// 获取名为 name 的 histogram，不存在时用 bounds 创建
+    /// 已经存在时 bounds 必须和第一次注册的一致
+    pub fn histogram(&self, name: impl AsRef<str>, bounds: &[f64]) -> Result<Histogram> {
+        self.histogram_with_labels(name, &[], bounds)
+    }
+
+    /// label 不能叫 le，它是 Prometheus 中 histogram bucket 的上界
+    pub fn histogram_with_labels(
+        &self,
+        name: impl AsRef<str>,
+        labels: &[(&str, &str)],
+        bounds: &[f64],
+    ) -> Result<Histogram> {
+        let name = name.as_ref();
+        // Prometheus 输出 bucket 时用 le 作为上界的 label
+        if labels.iter().any(|(key, _)| *key == "le") {
+            return Err(anyhow!("histogram {} can not have a label named le", name));
+        }
+
+        match self.get_or_insert_with(name, labels, MetricKind::Histogram, || {
+            Ok(Metric::Histogram(Histogram::new(bounds)?))
+        })? {
+            Metric::Histogram(h) if h.bounds() == bounds => Ok(h),
+            Metric::Histogram(_) => Err(anyhow!(
+                "histogram {} already registered with different bounds",
+                name
+            )),
+            other => Err(kind_mismatch(name, other.kind(), MetricKind::Histogram)),
+        }
+    }
+
+    /// 设置 metric 的说明，Prometheus 格式输出时作为 # HELP
+    pub fn describe(&self, name: impl Into<String>, help: impl Into<String>) {
+        self.help.insert(name.into(), help.into());
+    }
+
+    /// 按名字排序的所有 metric family
+    pub fn collect(&self) -> Vec<FamilySnapshot> {
+        let mut families = self
+            .families()
+            .into_iter()
+            .map(|(name, family)| {
+                let mut metrics = family
+                    .metrics
+                    .iter()
+                    .map(|entry| (entry.key().clone(), entry.value().value()))
+                    .collect::<Vec<_>>();
+                metrics.sort_by(|a, b| a.0.cmp(&b.0));
+
+                FamilySnapshot {
+                    help: self.help.get(&name).map(|help| help.value().clone()),
+                    name,
+                    kind: family.kind,
+                    metrics,
+                }
+            })
+            .collect::<Vec<_>>();
+        families.sort_by(|a, b| a.name.cmp(&b.name));
+
+        families
+    }
+
+    /// 没有 label 的 metric 的值
+    pub fn get(&self, name: impl AsRef<str>) -> Option<MetricValue> {
+        self.get_with_labels(name, &[])
+    }
+
+    pub fn get_with_labels(
+        &self,
+        name: impl AsRef<str>,
+        labels: &[(&str, &str)],
+    ) -> Option<MetricValue> {
+        let family = self.data.get(name.as_ref())?.clone();
+        let labels = Labels::new(labels).ok()?;
+        let value = family.metrics.get(&labels)?.value().value();
+        Some(value)
+    }
+
+    /// 复制出当前所有 metrics 的值
+    /// 一致性：每个 metric 单独读取，不是全局一致的快照
+    pub fn snapshot(&self) -> HashMap<MetricKey, MetricValue> {
+        let mut snapshot = HashMap::new();
+        for (name, family) in self.families() {
+            for entry in family.metrics.iter() {
+                let key = MetricKey::new(name.clone(), entry.key().clone());
+                snapshot.insert(key, entry.value().value());
+            }
+        }
+
+        snapshot
+    }
+
+    /// 名为 name 的所有 label 组合的值相加，比如所有 page 的请求数之和
+    /// histogram 没有单个的值，不参与计算
+    pub fn sum(&self, name: impl AsRef<str>) -> f64 {
+        self.values(name.as_ref())
+            .into_iter()
+            .filter_map(|(_, value)| value.as_f64())
+            .sum()
+    }
+
+    /// 按 label 的值分组求和，比如 sum_by("req", "method") 得到每个 method 的请求数，其他 label 被合并掉
+    /// 没有这个 label 的 metric 不参与计算
+    pub fn sum_by(&self, name: impl AsRef<str>, label: &str) -> HashMap<String, f64> {
+        let mut result = HashMap::new();
+        for (labels, value) in self.values(name.as_ref()) {
+            if let (Some(label), Some(value)) = (labels.get(label), value.as_f64()) {
+                *result.entry(label.to_string()).or_insert(0.0) += value;
+            }
+        }
+
+        result
+    }
+
+    /// 把 other 当前的值合并到自己中：counter 相加，gauge 按 gauge 策略，histogram 按 bucket 相加
+    /// 比如每个 worker 一个 registry，定期合并到一个新建的 registry 中统一导出
+    /// 注意合并是把值加上去，同一个 registry 重复合并同一个 other 会重复计算
+    pub fn merge(&self, other: &MetricsRegistry, gauge: GaugeMerge) -> Result<()> {
+        self.merge_snapshot(&other.collect(), gauge)
+    }
+
+    /// 把 collect 得到的值合并到自己中，规则和 merge 相同
+    /// 种类或者 histogram bounds 不同的 metric 返回错误，出错之前的 metric 已经合并进来了
+    pub fn merge_snapshot(&self, families: &[FamilySnapshot], gauge: GaugeMerge) -> Result<()> {
+        for family in families {
+            if let Some(help) = &family.help {
+                self.help
+                    .entry(family.name.clone())
+                    .or_insert_with(|| help.clone());
+            }
+
+            for (labels, value) in &family.metrics {
+                let labels = labels.iter().collect::<Vec<_>>();
+                self.merge_value(&family.name, &labels, value, gauge)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 现在的值相对于 earlier（之前 collect 的结果）的变化，见 diff_families
+    /// 比如每隔一段时间 collect 一次，得到这段时间内新增的请求数和延迟分布
+    pub fn diff(&self, earlier: &[FamilySnapshot]) -> Result<Vec<FamilySnapshot>> {
+        diff_families(&self.collect(), earlier)
+    }
+
+    // 把一个值合并到对应的 metric 上，metric 不存在时创建
+    // Sum 的 gauge 是原子加法；Max / Min / Last 是先读后写，合并期间其他线程对同一个 gauge 的写入可能被覆盖
+    fn merge_value(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: &MetricValue,
+        gauge: GaugeMerge,
+    ) -> Result<()> {
+        // 自己还没有这个 gauge 时直接取 other 的值，否则 Min / Max 会和新建时的 0 比较
+        let gauge = match self.get_with_labels(name, labels) {
+            Some(_) => gauge,
+            None => GaugeMerge::Last,
+        };
+
+        match value {
+            MetricValue::Counter(v) => self.counter_with_labels(name, labels)?.add(*v),
+            MetricValue::Gauge(v) => {
+                let g = self.gauge_with_labels(name, labels)?;
+                match gauge {
+                    GaugeMerge::Sum => g.add(*v),
+                    _ => g.set(gauge.merge_i64(g.get(), *v)),
+                }
+            }
+            MetricValue::FloatGauge(v) => {
+                let g = self.float_gauge_with_labels(name, labels)?;
+                match gauge {
+                    GaugeMerge::Sum => g.add(*v),
+                    _ => g.set(gauge.merge_f64(g.get(), *v)),
+                }
+            }
+            MetricValue::Histogram(h) => self
+                .histogram_with_labels(name, labels, &h.bounds)?
+                .merge_snapshot(h)?,
+        }
+
+        Ok(())
+    }
+
+    // 名为 name 的所有 label 组合和对应的值
+    fn values(&self, name: &str) -> Vec<(Labels, MetricValue)> {
+        let family = match self.data.get(name) {
+            Some(family) => family.clone(),
+            None => return Vec::new(),
+        };
+
+        family
+            .metrics
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().value()))
+            .collect()
+    }
+
+    // 先把 family clone 出来再遍历里面的 metric，遍历期间不持有外层 DashMap 的锁
+    fn families(&self) -> Vec<(String, MetricFamily)> {
+        self.data
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect()
+    }
+
+    fn family(&self, name: &str, kind: MetricKind) -> Result<MetricFamily> {
+        // 先用 &str 查找，已经存在时不需要为 key 分配 String
+        let family = match self.data.get(name) {
+            Some(family) => family.clone(),
+            None => self
+                .data
+                .entry(name.to_string())
+                .or_insert_with(|| MetricFamily::new(kind))
+                .clone(),
+        };
+
+        if family.kind != kind {
+            return Err(kind_mismatch(name, family.kind, kind));
+        }
+
+        Ok(family)
+    }
+
+    fn get_or_insert_with(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        kind: MetricKind,
+        f: impl FnOnce() -> Result<Metric>,
+    ) -> Result<Metric> {
+        // 先校验 label，label 不合法时不会留下一个没有任何 metric 的 family
+        let labels = Labels::new(labels)?;
+
+        let existing = self.data.get(name).map(|family| family.clone());
+        let (family, metric) = match existing {
+            Some(family) => {
+                if family.kind != kind {
+                    return Err(kind_mismatch(name, family.kind, kind));
+                }
+                if let Some(metric) = family.metrics.get(&labels) {
+                    return Ok(metric.clone());
+                }
+                let metric = f()?;
+                (family, metric)
+            }
+            // family 还不存在时先创建 metric（比如校验 histogram 的 bounds），失败时不会注册 family
+            None => {
+                let metric = f()?;
+                (self.family(name, kind)?, metric)
+            }
+        };
+
+        // 两个线程可能同时走到这里，entry 保证只有一个能插入成功
+        let metric = match family.metrics.entry(labels) {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => entry.insert(metric).clone(),
+        };
+
+        Ok(metric)
+    }
+}
+
+impl MetricFamily {
+    fn new(kind: MetricKind) -> Self {
+        Self {
+            kind,
+            metrics: Arc::new(DashMap::new()),
+        }
+    }
+}
+
+fn kind_mismatch(name: &str, actual: MetricKind, expected: MetricKind) -> anyhow::Error {
+    anyhow!(
+        "metric {} is registered as {}, not {}",
+        name,
+        actual,
+        expected
+    )
+}
+
+fn kind_mismatch_value(a: MetricKind, b: MetricKind) -> anyhow::Error {
+    anyhow!("metric kind mismatch: {} and {}", a, b)
+}
+
+impl Display for MetricKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+            MetricKind::FloatGauge => "float gauge",
+            MetricKind::Histogram => "histogram",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+impl Display for MetricValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MetricValue::Counter(v) => write!(f, "{}", v),
+            MetricValue::Gauge(v) => write!(f, "{}", v),
+            MetricValue::FloatGauge(v) => write!(f, "{}", v),
+            MetricValue::Histogram(h) => write!(f, "count={} sum={}", h.count, h.sum),
+        }
+    }
+}
+
+impl Display for MetricsRegistry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (key, value) in self.snapshot() {
+            writeln!(f, "{}: {}", key, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_registry_kinds() -> Result<()> {
+        let registry = MetricsRegistry::new();
+
+        registry.counter("req.total")?.add(3);
+        registry.gauge("req.in_flight")?.inc();
+        registry.float_gauge("cpu.usage")?.set(0.5);
+        registry.histogram("req.latency", &[0.1, 1.0])?.record(0.2);
+
+        assert_eq!(registry.get("req.total"), Some(MetricValue::Counter(3)));
+        assert_eq!(registry.get("req.in_flight"), Some(MetricValue::Gauge(1)));
+        assert_eq!(
+            registry.get("cpu.usage"),
+            Some(MetricValue::FloatGauge(0.5))
+        );
+        assert_eq!(registry.get("unknown"), None);
+
+        match registry.get("req.latency") {
+            Some(MetricValue::Histogram(h)) => {
+                assert_eq!(h.buckets, vec![0, 1, 0]);
+                assert_eq!(h.sum, 0.2);
+            }
+            other => panic!("unexpected value: {:?}", other),
+        }
+
+        assert_eq!(registry.snapshot().len(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry_labels() -> Result<()> {
+        let registry = MetricsRegistry::new();
+
+        // 句柄只需要获取一次，之后的 inc 不再查找 map
+        let get_1 = registry.counter_with_labels("req", &[("page", "1"), ("method", "GET")])?;
+        let get_2 = registry.counter_with_labels("req", &[("page", "2"), ("method", "GET")])?;
+        let post_1 = registry.counter_with_labels("req", &[("method", "POST"), ("page", "1")])?;
+
+        get_1.add(3);
+        get_2.add(5);
+        post_1.add(7);
+
+        // label 的顺序不影响拿到的是哪个 metric
+        registry
+            .counter_with_labels("req", &[("method", "GET"), ("page", "1")])?
+            .inc();
+
+        assert_eq!(
+            registry.get_with_labels("req", &[("page", "1"), ("method", "GET")]),
+            Some(MetricValue::Counter(4))
+        );
+        assert_eq!(registry.get("req"), None);
+
+        assert_eq!(registry.sum("req"), 16.0);
+        assert_eq!(registry.sum("unknown"), 0.0);
+
+        let by_page = registry.sum_by("req", "page");
+        assert_eq!(by_page.len(), 2);
+        assert_eq!(by_page["1"], 11.0);
+        assert_eq!(by_page["2"], 5.0);
+
+        let by_method = registry.sum_by("req", "method");
+        assert_eq!(by_method["GET"], 9.0);
+        assert_eq!(by_method["POST"], 7.0);
+
+        let key = MetricKey::new("req", Labels::new(&[("page", "2"), ("method", "GET")])?);
+        assert_eq!(registry.snapshot()[&key], MetricValue::Counter(5));
+
+        // 同一个名字下不同的 label 组合也必须是同一种 metric
+        assert!(registry.gauge_with_labels("req", &[("page", "3")]).is_err());
+        assert!(registry
+            .counter_with_labels("req", &[("page", "1"), ("page", "2")])
+            .is_err());
+
+        // label 不合法时不会注册 family，之后这个名字还可以注册成其他种类
+        assert!(registry
+            .counter_with_labels("y", &[("a", "1"), ("a", "2")])
+            .is_err());
+        assert!(registry.collect().iter().all(|f| f.name != "y"));
+        registry.gauge("y")?.set(1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry_kind_mismatch() -> Result<()> {
+        let registry = MetricsRegistry::new();
+        registry.counter("a")?;
+        registry.histogram("h", &[1.0])?;
+
+        assert!(registry.gauge("a").is_err());
+        assert!(registry.float_gauge("a").is_err());
+        assert!(registry.histogram("a", &[1.0]).is_err());
+        assert!(registry.counter("h").is_err());
+        assert!(registry.histogram("h", &[2.0]).is_err());
+        assert!(registry.histogram("h", &[1.0]).is_ok());
+
+        // bounds 不合法时不会注册 family，之后这个名字还可以注册成其他种类
+        assert!(registry.histogram("x", &[2.0, 1.0]).is_err());
+        assert!(registry
+            .histogram_with_labels("x", &[("le", "1")], &[1.0])
+            .is_err());
+        assert!(registry.collect().iter().all(|f| f.name != "x"));
+        registry.counter("x")?.inc();
+        assert_eq!(registry.get("x"), Some(MetricValue::Counter(1)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry_concurrent_registration() {
+        let registry = MetricsRegistry::new();
+
+        // 多个线程同时注册同一个名字，拿到的都是同一个 counter
+        thread::scope(|s| {
+            for _ in 0..8 {
+                let registry = registry.clone();
+                s.spawn(move || {
+                    for _ in 0..1_000 {
+                        registry.counter("shared").unwrap().inc();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(registry.get("shared"), Some(MetricValue::Counter(8_000)));
+    }
+
+    // 模拟一个 worker 的 registry
+    fn worker(requests: u64, in_flight: i64, latency: f64) -> Result<MetricsRegistry> {
+        let registry = MetricsRegistry::new();
+        registry
+            .counter_with_labels("req", &[("method", "GET")])?
+            .add(requests);
+        registry.gauge("in_flight")?.set(in_flight);
+        registry.histogram("latency", &[0.1, 1.0])?.record(latency);
+        Ok(registry)
+    }
+
+    #[test]
+    fn test_registry_merge() -> Result<()> {
+        let workers = [worker(3, 2, 0.05)?, worker(5, 7, 0.5)?];
+        workers[1]
+            .counter_with_labels("req", &[("method", "POST")])?
+            .inc();
+
+        for (policy, in_flight) in [
+            (GaugeMerge::Sum, 9),
+            (GaugeMerge::Max, 7),
+            (GaugeMerge::Min, 2),
+            (GaugeMerge::Last, 7),
+        ] {
+            let total = MetricsRegistry::new();
+            for worker in &workers {
+                total.merge(worker, policy)?;
+            }
+
+            assert_eq!(
+                total.get_with_labels("req", &[("method", "GET")]),
+                Some(MetricValue::Counter(8))
+            );
+            assert_eq!(total.sum("req"), 9.0);
+            assert_eq!(total.get("in_flight"), Some(MetricValue::Gauge(in_flight)));
+            match total.get("latency") {
+                Some(MetricValue::Histogram(h)) => {
+                    assert_eq!(h.buckets, vec![1, 1, 0]);
+                    assert_eq!(h.sum, 0.55);
+                }
+                other => panic!("unexpected value: {:?}", other),
+            }
+
+            // 合并 snapshot 得到的结果和合并 registry 相同
+            let mut families = workers[0].collect();
+            merge_families(&mut families, &workers[1].collect(), policy)?;
+            assert_eq!(families, total.collect());
+        }
+
+        // 种类或者 bounds 不同时不能合并
+        let other = MetricsRegistry::new();
+        other.counter("in_flight")?.inc();
+        assert!(workers[0].merge(&other, GaugeMerge::Sum).is_err());
+        let other = MetricsRegistry::new();
+        other.histogram("latency", &[1.0])?;
+        assert!(workers[0].merge(&other, GaugeMerge::Sum).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry_diff() -> Result<()> {
+        let registry = worker(3, 2, 0.05)?;
+        let earlier = registry.collect();
+
+        registry
+            .counter_with_labels("req", &[("method", "GET")])?
+            .add(4);
+        registry
+            .counter_with_labels("req", &[("method", "POST")])?
+            .inc();
+        registry.gauge("in_flight")?.set(1);
+        registry.histogram("latency", &[0.1, 1.0])?.record(5.0);
+        registry.counter("errors")?.inc();
+
+        let diff = registry.diff(&earlier)?;
+        let value = |name: &str, labels: &[(&str, &str)]| {
+            let family = diff.iter().find(|f| f.name == name).unwrap();
+            let labels = Labels::new(labels).unwrap();
+            let (_, value) = family.metrics.iter().find(|(l, _)| *l == labels).unwrap();
+            value.clone()
+        };
+
+        assert_eq!(value("req", &[("method", "GET")]), MetricValue::Counter(4));
+        assert_eq!(value("req", &[("method", "POST")]), MetricValue::Counter(1));
+        // gauge 是现在的值
+        assert_eq!(value("in_flight", &[]), MetricValue::Gauge(1));
+        assert_eq!(value("errors", &[]), MetricValue::Counter(1));
+        match value("latency", &[]) {
+            MetricValue::Histogram(h) => assert_eq!(h.buckets, vec![0, 0, 1]),
+            other => panic!("unexpected value: {:?}", other),
+        }
+
+        // counter 比 earlier 小时当作被重置过
+        let restarted = worker(1, 0, 0.05)?;
+        let diff = restarted.diff(&earlier)?;
+        let req = diff.iter().find(|f| f.name == "req").unwrap();
+        assert_eq!(req.metrics[0].1, MetricValue::Counter(1));
+
+        Ok(())
+    }
+}
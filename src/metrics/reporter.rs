@@ -0,0 +1,494 @@
+use super::worker::BackgroundWorker;
+use crate::{FamilySnapshot, MetricKind, MetricValue, Metrics, MetricsExporter, MetricsRegistry};
+#[cfg(feature = "tokio")]
+use anyhow::anyhow;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+#[cfg(feature = "tokio")]
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// 一次定期汇报的内容：每个 key 的当前值，以及和上一次汇报相比的变化
+/// entries 按 key 排序
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    /// 距离上一次汇报（第一次是 Reporter 启动）经过的时间
+    pub elapsed: Duration,
+    /// counter 和 gauge 的值，float gauge 和 histogram 只在 families 中
+    pub entries: Vec<ReportEntry>,
+    /// 这次汇报时数据源的完整快照，保留了 metric 的种类和 label，是 exporter 的输入
+    pub families: Vec<FamilySnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportEntry {
+    /// 没有 label 时是名字，否则是名字加上 label，比如 `req{page="1"}`
+    pub key: String,
+    pub kind: MetricKind,
+    pub value: i64,
+    /// 和上一次汇报相比的变化，第一次出现的 key 相当于从 0 开始
+    pub delta: i64,
+    /// 每秒的变化量：delta / elapsed
+    pub rate: f64,
+}
+
+/// Reporter 汇报的数据源，AtomicMetrics / ConcurrentMetrics（所有实现了 Metrics 的类型）和 MetricsRegistry 都可以
+pub trait ReportSource: Send + 'static {
+    /// 当前所有 metric 的值，按名字排序
+    fn collect(&self) -> Vec<FamilySnapshot>;
+}
+
+/// Report 的输出目标
+/// 内置了 StdoutSink / TracingSink / FileSink / ExporterSink，闭包 FnMut(&Report) -> Result<()> 也可以直接作为 sink
+pub trait ReportSink: Send + 'static {
+    fn report(&mut self, report: &Report) -> Result<()>;
+}
+
+/// 打印到标准输出，格式见 Report 的 Display
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdoutSink;
+
+/// 每个 key 输出一条 info 级别的 tracing 日志
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingSink;
+
+/// 以追加的方式写入文件，每次汇报之后 flush
+#[derive(Debug)]
+pub struct FileSink {
+    file: File,
+}
+
+/// 把 Report 交给 MetricsExporter 编码，再写到 writer 中
+/// 数据源是 MetricsRegistry 时 counter 仍然是 counter（比如 StatsD 输出 `|c`）；
+/// Metrics 的 key 没有种类信息，都是 gauge，见 ReportSource 的实现
+pub struct ExporterSink<E, W> {
+    exporter: E,
+    writer: W,
+}
+
+/// 定期对 Metrics 或者 MetricsRegistry 取快照并交给 sink 的后台线程，代替 `loop { sleep; println!("{}", metrics) }`
+/// 调用 stop 或者 drop 时会停止后台线程，停止之前会再汇报一次
+pub struct Reporter {
+    worker: BackgroundWorker,
+}
+
+/// 和 Reporter 一样，但是运行在 tokio task 中，需要在 tokio 运行时中创建
+/// drop 时只通知 task 停止，不等待；需要等最后一次汇报完成时调用 shutdown
+/// 需要打开 tokio feature（http feature 会打开它）
+#[cfg(feature = "tokio")]
+pub struct TaskReporter {
+    shutdown: watch::Sender<bool>,
+    handle: tokio::task::JoinHandle<()>,
+}
+
+// 记录上一次的快照，用来计算 delta 和 rate
+struct ReportState<S> {
+    source: S,
+    last: HashMap<String, i64>,
+    last_at: Instant,
+}
+
+/// 数据来自 Metrics::families：每个 key 是一个没有 label 的 gauge，ConcurrentMetrics 的 timer 是 histogram
+/// 需要 counter 语义（比如 Prometheus 的 counter、StatsD 的 `|c`）时用 MetricsRegistry 作为数据源
+impl<M: Metrics> ReportSource for M {
+    fn collect(&self) -> Vec<FamilySnapshot> {
+        self.families()
+    }
+}
+
+impl ReportSource for MetricsRegistry {
+    fn collect(&self) -> Vec<FamilySnapshot> {
+        MetricsRegistry::collect(self)
+    }
+}
+
+impl Display for Report {
+    // req.page.1: 10 (+3, 0.60/s)
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for entry in &self.entries {
+            writeln!(
+                f,
+                "{}: {} ({:+}, {:.2}/s)",
+                entry.key, entry.value, entry.delta, entry.rate
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ReportSink for StdoutSink {
+    fn report(&mut self, report: &Report) -> Result<()> {
+        println!("{}", report);
+        Ok(())
+    }
+}
+
+impl ReportSink for TracingSink {
+    fn report(&mut self, report: &Report) -> Result<()> {
+        for entry in &report.entries {
+            info!(
+                key = %entry.key,
+                value = entry.value,
+                delta = entry.delta,
+                rate = entry.rate,
+                "metrics report"
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl FileSink {
+    /// 文件不存在时会创建
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file })
+    }
+}
+
+impl ReportSink for FileSink {
+    fn report(&mut self, report: &Report) -> Result<()> {
+        writeln!(self.file, "{}", report)?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+impl<E, W> ExporterSink<E, W>
+where
+    E: MetricsExporter + 'static,
+    W: Write + Send + 'static,
+{
+    pub fn new(exporter: E, writer: W) -> Self {
+        Self { exporter, writer }
+    }
+}
+
+impl<E, W> ReportSink for ExporterSink<E, W>
+where
+    E: MetricsExporter + 'static,
+    W: Write + Send + 'static,
+{
+    fn report(&mut self, report: &Report) -> Result<()> {
+        let output = self.exporter.export(&report.families);
+        self.writer.write_all(output.as_bytes())?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<F> ReportSink for F
+where
+    F: FnMut(&Report) -> Result<()> + Send + 'static,
+{
+    fn report(&mut self, report: &Report) -> Result<()> {
+        self(report)
+    }
+}
+
+impl<S: ReportSource> ReportState<S> {
+    fn new(source: S) -> Self {
+        Self {
+            source,
+            last: HashMap::new(),
+            last_at: Instant::now(),
+        }
+    }
+
+    fn next(&mut self) -> Report {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_at);
+        let seconds = elapsed.as_secs_f64();
+        let families = self.source.collect();
+
+        let mut current = HashMap::new();
+        let mut entries = Vec::new();
+        for family in &families {
+            for (labels, value) in &family.metrics {
+                let value = match value {
+                    MetricValue::Counter(v) => i64::try_from(*v).unwrap_or(i64::MAX),
+                    MetricValue::Gauge(v) => *v,
+                    MetricValue::FloatGauge(_) | MetricValue::Histogram(_) => continue,
+                };
+                let key = if labels.is_empty() {
+                    family.name.clone()
+                } else {
+                    format!("{}{}", family.name, labels)
+                };
+
+                let delta = value - self.last.get(&key).copied().unwrap_or(0);
+                let rate = if seconds > 0.0 {
+                    delta as f64 / seconds
+                } else {
+                    0.0
+                };
+
+                current.insert(key.clone(), value);
+                entries.push(ReportEntry {
+                    key,
+                    kind: family.kind,
+                    value,
+                    delta,
+                    rate,
+                });
+            }
+        }
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+
+        self.last = current;
+        self.last_at = now;
+
+        Report {
+            elapsed,
+            entries,
+            families,
+        }
+    }
+
+    fn emit(&mut self, sink: &mut impl ReportSink) {
+        let report = self.next();
+        if let Err(e) = sink.report(&report) {
+            warn!("metrics report error: {}", e);
+        }
+    }
+}
+
+impl Reporter {
+    /// 启动后台线程，每隔 interval 汇报一次
+    pub fn spawn<S: ReportSource>(
+        source: S,
+        interval: Duration,
+        mut sink: impl ReportSink,
+    ) -> Self {
+        let mut state = ReportState::new(source);
+        let worker = BackgroundWorker::spawn("reporter", interval, move || state.emit(&mut sink));
+
+        Self { worker }
+    }
+
+    /// 停止后台线程，等它汇报完最后一次再返回
+    pub fn stop(mut self) -> Result<()> {
+        self.worker.stop()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl TaskReporter {
+    /// 在当前的 tokio 运行时中启动 task，每隔 interval 汇报一次
+    /// sink 是同步调用的，不要在 sink 中做耗时的 IO
+    pub fn spawn<S: ReportSource>(
+        source: S,
+        interval: Duration,
+        mut sink: impl ReportSink,
+    ) -> Self {
+        let (shutdown, mut shutdown_rx) = watch::channel(false);
+        let handle = tokio::spawn(async move {
+            let mut state = ReportState::new(source);
+            // tokio 的 interval 第一次 tick 是立即完成的，从一个 interval 之后开始
+            let start = tokio::time::Instant::now() + interval;
+            let mut ticker = tokio::time::interval_at(start, interval);
+
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => state.emit(&mut sink),
+                    // 收到停止信号或者 TaskReporter 被 drop 时，changed 都会返回
+                    _ = shutdown_rx.changed() => break,
+                }
+            }
+
+            state.emit(&mut sink);
+        });
+
+        Self { shutdown, handle }
+    }
+
+    /// 通知 task 停止，等它汇报完最后一次再返回
+    pub async fn shutdown(self) -> Result<()> {
+        let _ = self.shutdown.send(true);
+        self.handle
+            .await
+            .map_err(|e| anyhow!("reporter task error: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConcurrentMetrics, JsonExporter, PrometheusExporter, StatsdExporter};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    fn collect_reports() -> (Arc<Mutex<Vec<Report>>>, impl ReportSink) {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = {
+            let reports = reports.clone();
+            move |report: &Report| {
+                reports.lock().unwrap().push(report.clone());
+                Ok(())
+            }
+        };
+
+        (reports, sink)
+    }
+
+    #[test]
+    fn test_report_state_deltas() -> Result<()> {
+        let metrics = ConcurrentMetrics::new();
+        metrics.add("req", 10)?;
+        let mut state = ReportState::new(metrics.clone());
+
+        let report = state.next();
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].value, 10);
+        assert_eq!(report.entries[0].delta, 10);
+
+        metrics.add("req", 5)?;
+        metrics.set("in_flight", 2)?;
+        thread::sleep(Duration::from_millis(10));
+        let report = state.next();
+        let keys = report
+            .entries
+            .iter()
+            .map(|e| e.key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["in_flight", "req"]);
+        assert_eq!(report.entries[1].value, 15);
+        assert_eq!(report.entries[1].delta, 5);
+        let expected_rate = 5.0 / report.elapsed.as_secs_f64();
+        assert!((report.entries[1].rate - expected_rate).abs() < 1e-9);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_report_display_and_exporter_sink() -> Result<()> {
+        let metrics = ConcurrentMetrics::new();
+        metrics.add("req", 10)?;
+        let report = ReportState::new(metrics).next();
+        assert_eq!(report.entries[0].kind, MetricKind::Gauge);
+        assert!(format!("{}", report).starts_with("req: 10 (+10, "));
+
+        let mut sink = ExporterSink::new(JsonExporter::compact(), Vec::new());
+        sink.report(&report)?;
+        let value = serde_json::from_slice::<serde_json::Value>(&sink.writer)?;
+        assert_eq!(value[0]["name"], "req");
+        assert_eq!(value[0]["type"], "gauge");
+        assert_eq!(value[0]["metrics"][0]["value"], 10);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry_report_keeps_kinds() -> Result<()> {
+        let registry = MetricsRegistry::new();
+        registry
+            .counter_with_labels("req", &[("page", "1")])?
+            .add(3);
+        registry.gauge("in_flight")?.set(2);
+        registry.float_gauge("load")?.set(0.5);
+        let mut state = ReportState::new(registry.clone());
+
+        let report = state.next();
+        let entries = report
+            .entries
+            .iter()
+            .map(|e| (e.key.as_str(), e.kind, e.value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                ("in_flight", MetricKind::Gauge, 2),
+                ("req{page=\"1\"}", MetricKind::Counter, 3),
+            ]
+        );
+        // float gauge 不在 entries 中，但是 exporter 仍然能拿到
+        assert_eq!(report.families.len(), 3);
+
+        registry
+            .counter_with_labels("req", &[("page", "1")])?
+            .add(2);
+        let report = state.next();
+        assert_eq!(report.entries[1].delta, 2);
+
+        // counter 经过 Report 之后仍然是 counter
+        let mut sink = ExporterSink::new(StatsdExporter::new(""), Vec::new());
+        sink.report(&report)?;
+        let output = String::from_utf8(sink.writer)?;
+        assert!(output.contains("req:5|c"), "{}", output);
+        assert!(!output.contains(".rate"));
+
+        let mut sink = ExporterSink::new(PrometheusExporter::new(), Vec::new());
+        sink.report(&report)?;
+        let output = String::from_utf8(sink.writer)?;
+        assert!(output.contains("# TYPE req counter"), "{}", output);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reporter_stops_on_drop() -> Result<()> {
+        let metrics = ConcurrentMetrics::new();
+        let (reports, sink) = collect_reports();
+
+        let reporter = Reporter::spawn(metrics.clone(), Duration::from_millis(10), sink);
+        metrics.add("req", 3)?;
+        thread::sleep(Duration::from_millis(50));
+        metrics.add("req", 2)?;
+        drop(reporter);
+
+        // drop 时会等最后一次汇报完成，所有 delta 加起来就是最终的值
+        let reports = reports.lock().unwrap();
+        assert!(reports.len() >= 2);
+        let total = reports
+            .iter()
+            .flat_map(|r| &r.entries)
+            .map(|e| e.delta)
+            .sum::<i64>();
+        assert_eq!(total, 5);
+        assert_eq!(reports.last().unwrap().entries[0].value, 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reporter_with_registry() -> Result<()> {
+        let registry = MetricsRegistry::new();
+        let (reports, sink) = collect_reports();
+
+        let reporter = Reporter::spawn(registry.clone(), Duration::from_millis(10), sink);
+        registry.counter("req")?.add(3);
+        reporter.stop()?;
+
+        let reports = reports.lock().unwrap();
+        let last = reports.last().unwrap();
+        assert_eq!(last.entries[0].kind, MetricKind::Counter);
+        assert_eq!(last.entries[0].value, 3);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_task_reporter() -> Result<()> {
+        let metrics = ConcurrentMetrics::new();
+        let (reports, sink) = collect_reports();
+
+        let reporter = TaskReporter::spawn(metrics.clone(), Duration::from_millis(10), sink);
+        metrics.add("req", 3)?;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        reporter.shutdown().await?;
+
+        let reports = reports.lock().unwrap();
+        assert!(reports.len() >= 2);
+        assert_eq!(reports.last().unwrap().entries[0].value, 3);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{JsonExporter, MetricsExporter, MetricsRegistry, PrometheusExporter};
+use anyhow::{anyhow, Result};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::watch;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+// 请求头最多读这么多字节，/metrics 的请求不会有 body
+const MAX_REQUEST_SIZE: usize = 1024 * 8;
+// accept 出错（比如文件描述符用完）之后等一会再试，避免空转
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+/// 默认的读取请求头的超时时间
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// 默认的关闭时等待已有连接处理完的时间
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 通过 HTTP 暴露 MetricsRegistry 的小服务
+/// - GET /metrics       Prometheus 文本格式
+/// - GET /metrics.json  JSON 格式
+///
+/// 每个连接只处理一个请求，响应后关闭连接
+/// 和 dummy_redis_server 一样，每个连接交给一个 tokio task 处理
+/// 请求头在 read_timeout 内没有读完的连接直接关闭，慢的或者空闲的客户端不会一直占着连接
+pub struct MetricsServer {
+    local_addr: SocketAddr,
+    shutdown: watch::Sender<bool>,
+    handle: JoinHandle<Result<()>>,
+}
+
+impl MetricsServer {
+    /// 绑定地址并在后台开始接受连接，需要在 tokio 运行时中调用
+    /// 端口传 0 时由系统分配，通过 local_addr 获取实际的地址
+    pub async fn bind(addr: impl ToSocketAddrs, registry: MetricsRegistry) -> Result<Self> {
+        Self::bind_with_timeouts(
+            addr,
+            registry,
+            DEFAULT_READ_TIMEOUT,
+            DEFAULT_SHUTDOWN_TIMEOUT,
+        )
+        .await
+    }
+
+    /// 和 bind 一样，但是指定读取请求头的超时时间，以及 shutdown 时等待已有连接的时间
+    pub async fn bind_with_timeouts(
+        addr: impl ToSocketAddrs,
+        registry: MetricsRegistry,
+        read_timeout: Duration,
+        shutdown_timeout: Duration,
+    ) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        info!("metrics server listening on: {}", local_addr);
+
+        let (shutdown, shutdown_rx) = watch::channel(false);
+        let timeouts = Timeouts {
+            read: read_timeout,
+            shutdown: shutdown_timeout,
+        };
+        let handle = tokio::spawn(serve(listener, registry, timeouts, shutdown_rx));
+
+        Ok(Self {
+            local_addr,
+            shutdown,
+            handle,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// 优雅关闭：不再接受新的连接，等已经接受的连接处理完再返回
+    /// 超过 shutdown_timeout 还没有处理完的连接会被中止
+    pub async fn shutdown(self) -> Result<()> {
+        // 接收端已经退出时 send 会失败，这时服务已经停止了，忽略即可
+        let _ = self.shutdown.send(true);
+        self.handle
+            .await
+            .map_err(|e| anyhow!("metrics server task error: {}", e))?
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Timeouts {
+    read: Duration,
+    shutdown: Duration,
+}
+
+async fn serve(
+    listener: TcpListener,
+    registry: MetricsRegistry,
+    timeouts: Timeouts,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let mut connections = JoinSet::new();
+
+    loop {
+        tokio::select! {
+            result = listener.accept() => match result {
+                Ok((stream, client_addr)) => {
+                    let registry = registry.clone();
+                    connections.spawn(async move {
+                        if let Err(e) = process_connection(stream, &registry, timeouts.read).await {
+                            warn!("metrics connection {} error: {}", client_addr, e);
+                        }
+                    });
+                }
+                // 单个连接的错误（比如 ECONNABORTED、EMFILE）不应该让整个服务退出
+                Err(e) => {
+                    warn!("metrics server accept error: {}", e);
+                    tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                }
+            },
+            _ = shutdown.changed() => break,
+        }
+
+        // 回收已经结束的连接，避免 JoinSet 一直变大
+        while connections.try_join_next().is_some() {}
+    }
+
+    drop(listener);
+    let drain = async { while connections.join_next().await.is_some() {} };
+    if timeout(timeouts.shutdown, drain).await.is_err() {
+        warn!(
+            "metrics server abort {} connections after shutdown timeout",
+            connections.len()
+        );
+        connections.shutdown().await;
+    }
+    info!("metrics server stopped");
+
+    Ok(())
+}
+
+async fn process_connection(
+    mut stream: TcpStream,
+    registry: &MetricsRegistry,
+    read_timeout: Duration,
+) -> Result<()> {
+    let request_line = timeout(read_timeout, read_request_line(&mut stream))
+        .await
+        .map_err(|_| anyhow!("read request timeout"))??;
+    let response = match request_line {
+        Some(line) => route(&line, registry),
+        None => response(400, "text/plain", "bad request\n".to_string()),
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+// 读到请求头结束（空行），返回第一行，比如 "GET /metrics HTTP/1.1"
+async fn read_request_line(stream: &mut TcpStream) -> Result<Option<String>> {
+    let mut buf = Vec::with_capacity(1024);
+
+    loop {
+        if buf.windows(4).any(|w| w == b"\r\n\r\n") {
+            break;
+        }
+
+        if buf.len() >= MAX_REQUEST_SIZE {
+            return Ok(None);
+        }
+
+        // 0 代表 EOF，请求头还没有读完，对方就关闭了写
+        if stream.read_buf(&mut buf).await? == 0 {
+            return Ok(None);
+        }
+    }
+
+    let head = String::from_utf8_lossy(&buf);
+    Ok(head.lines().next().map(|line| line.to_string()))
+}
+
+fn route(request_line: &str, registry: &MetricsRegistry) -> String {
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method, target),
+        _ => return response(400, "text/plain", "bad request\n".to_string()),
+    };
+
+    if method != "GET" {
+        return response(405, "text/plain", "method not allowed\n".to_string());
+    }
+
+    // 忽略 query string
+    let path = target.split('?').next().unwrap_or(target);
+    match path {
+        "/metrics" => response(
+            200,
+            "text/plain; version=0.0.4; charset=utf-8",
+            PrometheusExporter::new().render(registry),
+        ),
+        "/metrics.json" => response(
+            200,
+            "application/json",
+            JsonExporter::compact().export_registry(registry),
+        ),
+        _ => response(404, "text/plain", "not found\n".to_string()),
+    }
+}
+
+fn response(status: u16, content_type: &str, body: String) -> String {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "",
+    };
+
+    format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        content_type,
+        body.len(),
+        body
+    )
+}
@@ -0,0 +1,171 @@
+use crate::{Clock, SystemClock};
+use anyhow::{anyhow, Result};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// 统计最近一段时间内事件的次数和速率，比如"最近 1 分钟每秒的请求数"
+///
+/// 窗口分成若干个长度为 resolution 的 bucket 组成一个环，每个 bucket 记录自己那段时间的次数
+/// 时间前进时，过期的 bucket 被清零重用；窗口包括当前还没有结束的 bucket，
+/// 所以实际统计的时间在 window - resolution 和 window 之间，resolution 越小越精确，内存也越多
+///
+/// 和 Meter 的移动平均不同，窗口之外的事件完全不算，窗口之内的事件权重相同
+/// 内部用一个 Mutex 保护 bucket 环，写入很频繁时用 Meter 或者 counter
+/// clone 是对 Arc 进行 clone
+#[derive(Debug, Clone)]
+pub struct SlidingWindow {
+    inner: Arc<SlidingWindowInner>,
+}
+
+#[derive(Debug)]
+struct SlidingWindowInner {
+    clock: Arc<dyn Clock>,
+    window: Duration,
+    resolution: Duration,
+    state: Mutex<WindowState>,
+}
+
+#[derive(Debug)]
+struct WindowState {
+    buckets: Vec<u64>,
+    // 当前 bucket 的编号：now / resolution
+    head: u64,
+}
+
+impl SlidingWindow {
+    /// window 必须是 resolution 的整数倍
+    pub fn new(window: Duration, resolution: Duration) -> Result<Self> {
+        Self::with_clock(window, resolution, Arc::new(SystemClock::new()))
+    }
+
+    pub fn with_clock(
+        window: Duration,
+        resolution: Duration,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self> {
+        if resolution.is_zero() || window < resolution {
+            return Err(anyhow!("window must not be shorter than resolution"));
+        }
+        if window.as_nanos() % resolution.as_nanos() != 0 {
+            return Err(anyhow!("window must be a multiple of resolution"));
+        }
+
+        let len = (window.as_nanos() / resolution.as_nanos()) as usize;
+        let head = bucket_of(clock.now(), resolution);
+
+        Ok(Self {
+            inner: Arc::new(SlidingWindowInner {
+                clock,
+                window,
+                resolution,
+                state: Mutex::new(WindowState {
+                    buckets: vec![0; len],
+                    head,
+                }),
+            }),
+        })
+    }
+
+    pub fn mark(&self) {
+        self.mark_n(1)
+    }
+
+    pub fn mark_n(&self, n: u64) {
+        self.with_state(|state| {
+            let idx = (state.head % state.buckets.len() as u64) as usize;
+            state.buckets[idx] += n;
+        })
+    }
+
+    /// 窗口内的总次数
+    pub fn sum(&self) -> u64 {
+        self.with_state(|state| state.buckets.iter().sum())
+    }
+
+    /// 窗口内每秒的次数：sum / window
+    pub fn rate(&self) -> f64 {
+        self.sum() as f64 / self.inner.window.as_secs_f64()
+    }
+
+    pub fn window(&self) -> Duration {
+        self.inner.window
+    }
+
+    // 先把环推进到当前时间，再在锁内执行 f
+    fn with_state<T>(&self, f: impl FnOnce(&mut WindowState) -> T) -> T {
+        let inner = &self.inner;
+        let now = bucket_of(inner.clock.now(), inner.resolution);
+
+        let mut state = inner.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.advance(now);
+        f(&mut state)
+    }
+}
+
+impl WindowState {
+    // 清零 head 之后到 now 之间的 bucket，超过一圈时整个环都清零
+    fn advance(&mut self, now: u64) {
+        if now <= self.head {
+            return;
+        }
+
+        let len = self.buckets.len() as u64;
+        let expired = (now - self.head).min(len);
+        for bucket in (now - expired + 1)..=now {
+            self.buckets[(bucket % len) as usize] = 0;
+        }
+        self.head = now;
+    }
+}
+
+fn bucket_of(time: Duration, resolution: Duration) -> u64 {
+    (time.as_nanos() / resolution.as_nanos()) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+
+    #[test]
+    fn test_sliding_window() -> Result<()> {
+        let clock = ManualClock::new();
+        let window = SlidingWindow::with_clock(
+            Duration::from_secs(60),
+            Duration::from_secs(1),
+            Arc::new(clock.clone()),
+        )?;
+
+        // 前 30 秒每秒 2 次
+        for _ in 0..30 {
+            window.mark_n(2);
+            clock.advance(Duration::from_secs(1));
+        }
+        assert_eq!(window.sum(), 60);
+        assert_eq!(window.rate(), 1.0);
+
+        // 再过 40 秒，现在是第 70 秒，窗口是第 11 到 70 秒，前 11 秒的事件移出窗口
+        clock.advance(Duration::from_secs(40));
+        assert_eq!(window.sum(), 2 * 19);
+
+        window.mark();
+        assert_eq!(window.sum(), 2 * 19 + 1);
+
+        // 超过一整个窗口没有事件，全部清零
+        clock.advance(Duration::from_secs(600));
+        assert_eq!(window.sum(), 0);
+        window.mark_n(5);
+        assert_eq!(window.sum(), 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_window() {
+        let second = Duration::from_secs(1);
+        assert!(SlidingWindow::new(second, Duration::ZERO).is_err());
+        assert!(SlidingWindow::new(second, second * 2).is_err());
+        assert!(SlidingWindow::new(second * 3, second * 2).is_err());
+        assert!(SlidingWindow::new(second * 4, second * 2).is_ok());
+    }
+}
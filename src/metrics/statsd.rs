@@ -0,0 +1,246 @@
+use crate::{FamilySnapshot, HistogramSnapshot, Labels, MetricValue, MetricsExporter};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// 把 metrics 快照输出成 StatsD 的行协议，每个 metric 一行：`name:value|type`
+/// label 用 DogStatsD 的 tag 扩展输出：`name:value|c|#method:GET,page:1`
+///
+/// - counter 输出 `|c`，值是和上一次 export 相比增加的部分，没有变化的 counter 不输出
+/// - gauge / float gauge 输出 `|g`，值是当前值
+/// - histogram 输出 `name.count` 和 `name.sum` 两个 `|c`，同样是和上一次相比的变化
+///   记录过负数时 sum 可能变小，这时 sum 的变化是负数，原样输出
+///
+/// StatsD 的 counter 是增量，所以 exporter 需要记住上一次的值，同一个 exporter 应该只给一个目标使用
+#[derive(Debug, Default)]
+pub struct StatsdExporter {
+    prefix: String,
+    // 上一次 export 时 counter 的值，key 是输出的 name 加上 tag
+    last: Mutex<LastValues>,
+}
+
+// counter 和 histogram 的 count 是整数，用 u64 计算增量，超过 2^53 也不会丢失精度
+// 只有 histogram 的 sum 本身是 f64
+#[derive(Debug, Default)]
+struct LastValues {
+    counts: HashMap<String, u64>,
+    sums: HashMap<String, f64>,
+}
+
+impl StatsdExporter {
+    /// prefix 会加在每个名字前面，比如 "app."，不需要时传空字符串
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            last: Mutex::new(LastValues::default()),
+        }
+    }
+}
+
+impl MetricsExporter for StatsdExporter {
+    fn export(&self, families: &[FamilySnapshot]) -> String {
+        let mut out = String::new();
+        // 只在这里加锁，同时 export 的调用互相不会算错增量
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+
+        for family in families {
+            let name = sanitize(&format!("{}{}", self.prefix, family.name));
+
+            for (labels, value) in &family.metrics {
+                let tags = format_tags(labels);
+                match value {
+                    MetricValue::Counter(v) => write_delta(&mut out, &mut last, &name, &tags, *v),
+                    MetricValue::Gauge(v) => write_gauge(&mut out, &name, &tags, *v as f64),
+                    MetricValue::FloatGauge(v) => write_gauge(&mut out, &name, &tags, *v),
+                    MetricValue::Histogram(h) => {
+                        write_histogram(&mut out, &mut last, &name, &tags, h)
+                    }
+                }
+            }
+        }
+
+        out
+    }
+}
+
+// counter 输出和上一次相比的增量，值变小说明 counter 被重置过，这时把当前值整个作为增量
+fn write_delta(out: &mut String, last: &mut LastValues, name: &str, tags: &str, value: u64) {
+    let previous = last
+        .counts
+        .insert(format!("{}{}", name, tags), value)
+        .unwrap_or_default();
+    let delta = if value >= previous {
+        value - previous
+    } else {
+        value
+    };
+
+    if delta != 0 {
+        let _ = writeln!(out, "{}:{}|c{}", name, delta, tags);
+    }
+}
+
+// count 只会增加，变小说明 histogram 被重置过，这时 count 和 sum 都把当前值整个作为增量
+// sum 不是单调的（可以记录负数），不能用它判断重置，变小时输出负的增量
+fn write_histogram(
+    out: &mut String,
+    last: &mut LastValues,
+    name: &str,
+    tags: &str,
+    h: &HistogramSnapshot,
+) {
+    let key = format!("{}{}", name, tags);
+    let previous_count = last.counts.insert(key.clone(), h.count).unwrap_or_default();
+    let previous_sum = last.sums.insert(key, h.sum).unwrap_or_default();
+    let (count, sum) = if h.count >= previous_count {
+        (h.count - previous_count, h.sum - previous_sum)
+    } else {
+        (h.count, h.sum)
+    };
+
+    if count != 0 {
+        let _ = writeln!(out, "{}.count:{}|c{}", name, count, tags);
+    }
+    if sum != 0.0 && sum.is_finite() {
+        let _ = writeln!(out, "{}.sum:{}|c{}", name, sum, tags);
+    }
+}
+
+fn write_gauge(out: &mut String, name: &str, tags: &str, value: f64) {
+    // NaN 和无穷大 StatsD 没法表示，直接跳过
+    if !value.is_finite() {
+        return;
+    }
+
+    // 以 - 或者 + 开头的 gauge 值会被当成是在当前值上加减，负数要先设置成 0 再减
+    if value < 0.0 {
+        let _ = writeln!(out, "{}:0|g{}", name, tags);
+    }
+    let _ = writeln!(out, "{}:{}|g{}", name, value, tags);
+}
+
+// |#k1:v1,k2:v2，没有 label 时什么都不输出
+fn format_tags(labels: &Labels) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+
+    let tags = labels
+        .iter()
+        .map(|(k, v)| format!("{}:{}", sanitize(k), sanitize_tag_value(v)))
+        .collect::<Vec<_>>();
+    format!("|#{}", tags.join(","))
+}
+
+/// 名字中的 `:` `|` `@` `#` `,` 和空白字符在行协议中有特殊含义，替换成 `_`
+pub fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            ':' | '|' | '@' | '#' | ',' => '_',
+            c if c.is_whitespace() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+// tag 的值中可以出现 `:`，只有 `|` `,` `#` 和空白字符需要替换
+fn sanitize_tag_value(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '|' | '#' | ',' => '_',
+            c if c.is_whitespace() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MetricsRegistry;
+    use anyhow::Result;
+
+    #[test]
+    fn test_statsd_exporter() -> Result<()> {
+        let registry = MetricsRegistry::new();
+        let req = registry.counter_with_labels("req", &[("page", "1"), ("method", "GET")])?;
+        req.add(3);
+        registry.gauge("in_flight")?.set(-2);
+        registry.float_gauge("cpu usage")?.set(0.25);
+        let latency = registry.histogram("latency", &[1.0])?;
+        latency.record(0.5);
+
+        let exporter = StatsdExporter::new("app.");
+        let expected = "\
+app.cpu_usage:0.25|g
+app.in_flight:0|g
+app.in_flight:-2|g
+app.latency.count:1|c
+app.latency.sum:0.5|c
+app.req:3|c|#method:GET,page:1
+";
+        assert_eq!(exporter.export_registry(&registry), expected);
+
+        // counter 只输出增量，没有变化的不输出，gauge 每次都输出
+        req.add(2);
+        let expected = "\
+app.cpu_usage:0.25|g
+app.in_flight:0|g
+app.in_flight:-2|g
+app.req:2|c|#method:GET,page:1
+";
+        assert_eq!(exporter.export_registry(&registry), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_statsd_large_counter() -> Result<()> {
+        let registry = MetricsRegistry::new();
+        let req = registry.counter("req")?;
+        req.add(1 << 60);
+
+        // 超过 2^53 之后 f64 无法表示 +1，增量必须用整数计算
+        let exporter = StatsdExporter::new("");
+        assert_eq!(
+            exporter.export_registry(&registry),
+            format!("req:{}|c\n", 1u64 << 60)
+        );
+        req.inc();
+        assert_eq!(exporter.export_registry(&registry), "req:1|c\n");
+        assert_eq!(exporter.export_registry(&registry), "");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_statsd_histogram_negative_sum() -> Result<()> {
+        let registry = MetricsRegistry::new();
+        let h = registry.histogram("temp", &[0.0])?;
+        h.record(3.0);
+
+        let exporter = StatsdExporter::new("");
+        assert_eq!(
+            exporter.export_registry(&registry),
+            "temp.count:1|c\ntemp.sum:3|c\n"
+        );
+
+        // sum 变小不是重置，只输出负的增量，不会把整个 sum 再发一遍
+        h.record(-5.0);
+        assert_eq!(
+            exporter.export_registry(&registry),
+            "temp.count:1|c\ntemp.sum:-5|c\n"
+        );
+        assert_eq!(exporter.export_registry(&registry), "");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("a:b|c@d#e f"), "a_b_c_d_e_f");
+        assert_eq!(sanitize("http.req_total"), "http.req_total");
+        assert_eq!(sanitize_tag_value("host:80|x"), "host:80_x");
+    }
+}
@@ -0,0 +1,165 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::thread;
+
+// 分片数量的上限，每个分片占 128 字节
+const MAX_SHARDS: usize = 64;
+
+// 给每个线程分配一个编号，按编号选择分片，线程之间轮流分到不同的分片
+static NEXT_THREAD_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static THREAD_ID: usize = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+}
+
+/// 对齐到 128 字节，保证两个值不会落在同一个 cache line 上
+/// x86 的 cache line 是 64 字节，但相邻的两个 cache line 会被一起预取，Apple M 系列的 cache line 是 128 字节
+#[derive(Default)]
+#[repr(align(128))]
+struct CachePadded<T>(T);
+
+/// 分片的计数器：每个线程写自己的分片，读的时候把所有分片加起来
+///
+/// 多个线程频繁写同一个 AtomicI64 时，这个 cache line 会在 CPU 之间来回传递，写入变成串行的
+/// 分片之后不同线程大多写不同的 cache line，写入可以随线程数扩展，代价是读取要遍历所有分片，内存也更多
+/// 适合写多读少的计数器，比如请求数
+///
+/// 内存顺序和 AtomicMetrics 一样，都是 Ordering::Relaxed
+/// add 不会丢失更新；get 是依次读取各个分片，有并发写入时读到的不是某一时刻的精确值
+pub struct StripedCounter {
+    shards: Box<[CachePadded<AtomicI64>]>,
+}
+
+impl StripedCounter {
+    /// 分片数是 CPU 核数向上取整到 2 的幂，最多 MAX_SHARDS 个
+    pub fn new() -> Self {
+        let cpus = thread::available_parallelism().map_or(4, |n| n.get());
+        Self::with_shards(cpus)
+    }
+
+    /// shards 会向上取整到 2 的幂，范围是 [1, MAX_SHARDS]
+    pub fn with_shards(shards: usize) -> Self {
+        let shards = shards.clamp(1, MAX_SHARDS).next_power_of_two();
+        let shards = (0..shards).map(|_| CachePadded::default()).collect();
+
+        Self { shards }
+    }
+
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn inc(&self) {
+        self.add(1)
+    }
+
+    pub fn dec(&self) {
+        self.add(-1)
+    }
+
+    pub fn add(&self, delta: i64) {
+        self.shard().fetch_add(delta, Ordering::Relaxed);
+    }
+
+    /// 所有分片的和
+    pub fn get(&self) -> i64 {
+        self.shards
+            .iter()
+            .map(|shard| shard.0.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// 把所有分片清零，再把 value 写到当前线程的分片上
+    /// 不是一个原子操作：和 set 同时发生的 add 可能被清掉，也可能保留
+    pub fn set(&self, value: i64) {
+        for shard in self.shards.iter() {
+            shard.0.store(0, Ordering::Relaxed);
+        }
+        self.add(value);
+    }
+
+    /// 依次把每个分片换成 0 并累加旧值，最后把 value 加到当前线程的分片上，返回旧值的和
+    /// 每个分片上的 swap 是原子的，所以和 swap 同时发生的 add 不会丢失：要么算在返回值里，要么留在计数器里
+    pub fn swap(&self, value: i64) -> i64 {
+        let previous = self
+            .shards
+            .iter()
+            .map(|shard| shard.0.swap(0, Ordering::Relaxed))
+            .sum();
+        self.add(value);
+
+        previous
+    }
+
+    fn shard(&self) -> &AtomicI64 {
+        // 分片数是 2 的幂，用 & 代替取模
+        let idx = THREAD_ID.with(|id| *id) & (self.shards.len() - 1);
+        &self.shards[idx].0
+    }
+}
+
+impl Default for StripedCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for StripedCounter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StripedCounter")
+            .field("shards", &self.shards.len())
+            .field("value", &self.get())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+
+    #[test]
+    fn test_shards_are_padded() {
+        assert_eq!(mem::align_of::<CachePadded<AtomicI64>>(), 128);
+        assert_eq!(mem::size_of::<CachePadded<AtomicI64>>(), 128);
+
+        assert_eq!(StripedCounter::with_shards(0).shards(), 1);
+        assert_eq!(StripedCounter::with_shards(3).shards(), 4);
+        assert_eq!(StripedCounter::with_shards(1_000).shards(), MAX_SHARDS);
+        assert!(StripedCounter::new().shards().is_power_of_two());
+    }
+
+    #[test]
+    fn test_concurrent_add_and_swap() {
+        const WRITERS: usize = 8;
+        const OPS: i64 = 10_000;
+
+        let counter = StripedCounter::with_shards(4);
+
+        // 和 AtomicMetrics 的测试一样：一边写一边 swap(0)，所有增量加起来等于总的写入次数
+        let drained = thread::scope(|s| {
+            for _ in 0..WRITERS {
+                s.spawn(|| {
+                    for _ in 0..OPS {
+                        counter.inc();
+                        counter.add(2);
+                        counter.dec();
+                    }
+                });
+            }
+
+            let mut drained = 0;
+            for _ in 0..1_000 {
+                drained += counter.swap(0);
+            }
+            drained
+        });
+
+        assert_eq!(drained + counter.get(), 2 * OPS * WRITERS as i64);
+
+        counter.set(-3);
+        assert_eq!(counter.get(), -3);
+        assert_eq!(counter.swap(5), -3);
+        assert_eq!(counter.get(), 5);
+    }
+}
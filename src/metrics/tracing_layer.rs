@@ -0,0 +1,185 @@
+use crate::{Counter, Histogram, MetricsRegistry};
+use anyhow::Result;
+use dashmap::DashMap;
+use std::time::Instant;
+use tracing::callsite::Identifier;
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// span 从创建到关闭的时间，单位是秒，label 是 span 和 target
+pub const SPAN_DURATION_METRIC: &str = "tracing.span.duration";
+/// event 的数量，label 是 target 和 level
+pub const EVENTS_METRIC: &str = "tracing.events";
+
+/// 默认的 span 时间的 bucket 上界，单位是秒，从 0.5ms 到 10s
+pub const DEFAULT_SPAN_BOUNDS: [f64; 10] =
+    [0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0];
+
+/// 把 tracing 的 span 和 event 记录到 MetricsRegistry 中的 Layer
+/// - 每个 span 关闭时，把从创建到关闭的时间记录到 SPAN_DURATION_METRIC 的 histogram 中
+/// - 每个 event 给 EVENTS_METRIC 的 counter 加 1
+///
+/// 这样用 `#[instrument]` 标注的函数自动就有了调用次数和耗时的分布
+/// span 的时间包括中间没有进入 span 的时间，比如 async 函数在 await 时被挂起的时间
+///
+/// metric 的句柄按 callsite（代码中的位置）缓存，同一个 span / event 第二次之后不再查找 registry
+/// 注册失败（比如同名的 metric 已经注册成其他种类）时这个 callsite 不再记录
+pub struct MetricsLayer {
+    registry: MetricsRegistry,
+    bounds: Vec<f64>,
+    spans: DashMap<Identifier, Option<Histogram>>,
+    events: DashMap<Identifier, Option<Counter>>,
+}
+
+// 保存在 span 的 extensions 中
+struct SpanStart(Instant);
+
+impl MetricsLayer {
+    pub fn new(registry: MetricsRegistry) -> Self {
+        registry.describe(
+            SPAN_DURATION_METRIC,
+            "Duration of tracing spans in seconds.",
+        );
+        registry.describe(EVENTS_METRIC, "Number of tracing events.");
+
+        Self {
+            registry,
+            bounds: DEFAULT_SPAN_BOUNDS.to_vec(),
+            spans: DashMap::new(),
+            events: DashMap::new(),
+        }
+    }
+
+    /// 修改 span 时间的 bucket 上界，要在第一个 span 关闭之前设置
+    /// bounds 和 MetricsRegistry::histogram 的要求一样，必须是有限的、严格递增的，否则返回错误
+    pub fn with_bounds(mut self, bounds: &[f64]) -> Result<Self> {
+        // 在这里校验，否则要等到 span 关闭时注册 histogram 才会失败，而且只是不再记录，没有任何错误
+        Histogram::new(bounds)?;
+        self.bounds = bounds.to_vec();
+        Ok(self)
+    }
+
+    fn span_histogram(&self, metadata: &'static Metadata<'static>) -> Option<Histogram> {
+        let callsite = metadata.callsite();
+        if let Some(histogram) = self.spans.get(&callsite) {
+            return histogram.clone();
+        }
+
+        let labels = [("span", metadata.name()), ("target", metadata.target())];
+        let histogram = self
+            .registry
+            .histogram_with_labels(SPAN_DURATION_METRIC, &labels, &self.bounds)
+            .ok();
+        self.spans.insert(callsite, histogram.clone());
+
+        histogram
+    }
+
+    fn event_counter(&self, metadata: &'static Metadata<'static>) -> Option<Counter> {
+        let callsite = metadata.callsite();
+        if let Some(counter) = self.events.get(&callsite) {
+            return counter.clone();
+        }
+
+        let level = metadata.level().as_str().to_ascii_lowercase();
+        let labels = [("target", metadata.target()), ("level", level.as_str())];
+        let counter = self
+            .registry
+            .counter_with_labels(EVENTS_METRIC, &labels)
+            .ok();
+        self.events.insert(callsite, counter.clone());
+
+        counter
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, _attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanStart(Instant::now()));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if let Some(counter) = self.event_counter(event.metadata()) {
+            counter.inc();
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(elapsed) = span.extensions().get::<SpanStart>().map(|s| s.0.elapsed()) else {
+            return;
+        };
+
+        if let Some(histogram) = self.span_histogram(span.metadata()) {
+            histogram.record(elapsed.as_secs_f64());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MetricValue;
+    use std::thread;
+    use std::time::Duration;
+    use tracing::{info, instrument, warn};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[instrument]
+    fn handle_request(page: u32) {
+        thread::sleep(Duration::from_millis(2));
+        info!("handled page {}", page);
+    }
+
+    #[test]
+    fn test_metrics_layer() -> Result<()> {
+        let registry = MetricsRegistry::new();
+        let layer = MetricsLayer::new(registry.clone()).with_bounds(&[0.001, 1.0])?;
+        let subscriber = tracing_subscriber::registry().with(layer);
+
+        tracing::subscriber::with_default(subscriber, || {
+            handle_request(1);
+            handle_request(2);
+            warn!(target: "app", "slow");
+        });
+
+        let target = module_path!();
+        let labels = [("span", "handle_request"), ("target", target)];
+        match registry.get_with_labels(SPAN_DURATION_METRIC, &labels) {
+            Some(MetricValue::Histogram(h)) => {
+                assert_eq!(h.count, 2);
+                // 每次至少 2ms，都落在 (0.001, 1.0] 这个 bucket 中
+                assert_eq!(h.buckets, vec![0, 2, 0]);
+                assert!(h.sum >= 0.004);
+            }
+            other => panic!("unexpected span metric: {:?}", other),
+        }
+
+        assert_eq!(
+            registry.get_with_labels(EVENTS_METRIC, &[("target", target), ("level", "info")]),
+            Some(MetricValue::Counter(2))
+        );
+        assert_eq!(
+            registry.get_with_labels(EVENTS_METRIC, &[("target", "app"), ("level", "warn")]),
+            Some(MetricValue::Counter(1))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_bounds() {
+        let layer = || MetricsLayer::new(MetricsRegistry::new());
+        assert!(layer().with_bounds(&[1.0, 0.001]).is_err());
+        assert!(layer().with_bounds(&[0.001, f64::INFINITY]).is_err());
+    }
+}
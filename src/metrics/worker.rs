@@ -0,0 +1,93 @@
+use anyhow::{anyhow, Result};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// 每隔 interval 在后台线程中调用一次 tick 的工作线程，Reporter 和 PushReporter 共用
+/// 调用 stop 或者 drop 时停止，停止之前会再调用一次 tick，保证最后的数据不会丢
+pub(crate) struct BackgroundWorker {
+    name: &'static str,
+    stop: Option<mpsc::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl BackgroundWorker {
+    /// name 只用于错误信息，比如 "reporter"
+    pub(crate) fn spawn(
+        name: &'static str,
+        interval: Duration,
+        mut tick: impl FnMut() + Send + 'static,
+    ) -> Self {
+        let (stop, stop_rx) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || loop {
+            // 收到停止信号或者 BackgroundWorker 被 drop（发送端断开）时，最后执行一次后退出
+            let stopped = match stop_rx.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => false,
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => true,
+            };
+
+            tick();
+
+            if stopped {
+                break;
+            }
+        });
+
+        Self {
+            name,
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+
+    /// 通知后台线程停止，等它执行完最后一次 tick 再返回，重复调用什么都不做
+    pub(crate) fn stop(&mut self) -> Result<()> {
+        if let Some(stop) = self.stop.take() {
+            // 线程已经退出时 send 会失败，忽略即可
+            let _ = stop.send(());
+        }
+        if let Some(handle) = self.handle.take() {
+            handle
+                .join()
+                .map_err(|_| anyhow!("{} thread panicked", self.name))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for BackgroundWorker {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_background_worker() -> Result<()> {
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let mut worker = BackgroundWorker::spawn("test", Duration::from_secs(60), {
+            let ticks = ticks.clone();
+            move || {
+                ticks.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+
+        // 停止时不用等 interval，立即执行最后一次
+        worker.stop()?;
+        assert_eq!(ticks.load(Ordering::Relaxed), 1);
+        worker.stop()?;
+        assert_eq!(ticks.load(Ordering::Relaxed), 1);
+
+        let mut worker =
+            BackgroundWorker::spawn("panic", Duration::from_secs(60), || panic!("tick"));
+        assert!(worker.stop().is_err());
+
+        Ok(())
+    }
+}
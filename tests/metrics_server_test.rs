@@ -0,0 +1,158 @@
+#![cfg(feature = "http")]
+
+use anyhow::Result;
+use rs_concurrency::{MetricsRegistry, MetricsServer};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+// 发送一个请求，读取完整的响应，服务端响应后会关闭连接
+async fn request(addr: SocketAddr, request: &str) -> Result<String> {
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    Ok(response)
+}
+
+async fn get(addr: SocketAddr, path: &str) -> Result<String> {
+    request(
+        addr,
+        &format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path),
+    )
+    .await
+}
+
+fn start_registry() -> Result<MetricsRegistry> {
+    let registry = MetricsRegistry::new();
+    registry.describe("req.total", "Total number of requests.");
+    registry
+        .counter_with_labels("req.total", &[("page", "1")])?
+        .add(3);
+    Ok(registry)
+}
+
+#[tokio::test]
+async fn test_metrics_endpoint() -> Result<()> {
+    let registry = start_registry()?;
+    let server = MetricsServer::bind("127.0.0.1:0", registry.clone()).await?;
+    let addr = server.local_addr();
+
+    let response = get(addr, "/metrics").await?;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Content-Type: text/plain; version=0.0.4"));
+    assert!(response.ends_with(
+        "# HELP req_total Total number of requests.\n# TYPE req_total counter\nreq_total{page=\"1\"} 3\n"
+    ));
+
+    // 服务读的是实时的值
+    registry
+        .counter_with_labels("req.total", &[("page", "1")])?
+        .inc();
+    let response = get(addr, "/metrics?debug=1").await?;
+    assert!(response.ends_with("req_total{page=\"1\"} 4\n"));
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn test_metrics_json_endpoint() -> Result<()> {
+    let server = MetricsServer::bind("127.0.0.1:0", start_registry()?).await?;
+
+    let response = get(server.local_addr(), "/metrics.json").await?;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Content-Type: application/json"));
+
+    let body = response.split("\r\n\r\n").nth(1).unwrap_or_default();
+    let json: serde_json::Value = serde_json::from_str(body)?;
+    assert_eq!(json[0]["name"], "req.total");
+    assert_eq!(json[0]["metrics"][0]["labels"]["page"], "1");
+    assert_eq!(json[0]["metrics"][0]["value"], 3);
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn test_not_found_and_method_not_allowed() -> Result<()> {
+    let server = MetricsServer::bind("127.0.0.1:0", MetricsRegistry::new()).await?;
+    let addr = server.local_addr();
+
+    let response = get(addr, "/other").await?;
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    let response = request(addr, "POST /metrics HTTP/1.1\r\n\r\n").await?;
+    assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn test_graceful_shutdown() -> Result<()> {
+    let server = MetricsServer::bind("127.0.0.1:0", start_registry()?).await?;
+    let addr = server.local_addr();
+
+    // 已经建立的连接在关闭后仍然能拿到响应
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(b"GET /metrics HTTP/1.1\r\n").await?;
+    // 等服务端 accept 这个连接
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let shutdown = tokio::spawn(server.shutdown());
+
+    stream.write_all(b"\r\n").await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    shutdown.await??;
+
+    // 关闭后不再接受新的连接
+    assert!(TcpStream::connect(addr).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_idle_connection_timeout() -> Result<()> {
+    let server = MetricsServer::bind_with_timeouts(
+        "127.0.0.1:0",
+        start_registry()?,
+        Duration::from_millis(100),
+        Duration::from_secs(5),
+    )
+    .await?;
+
+    // 只发了半个请求头，超时之后服务端关闭连接，不返回任何内容
+    let mut stream = TcpStream::connect(server.local_addr()).await?;
+    stream.write_all(b"GET /metrics HTTP/1.1\r\n").await?;
+    let mut response = String::new();
+    tokio::time::timeout(Duration::from_secs(2), stream.read_to_string(&mut response)).await??;
+    assert!(response.is_empty());
+
+    // 之后的请求不受影响
+    let response = get(server.local_addr(), "/metrics").await?;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn test_shutdown_aborts_idle_connections() -> Result<()> {
+    let server = MetricsServer::bind_with_timeouts(
+        "127.0.0.1:0",
+        start_registry()?,
+        Duration::from_secs(60),
+        Duration::from_millis(100),
+    )
+    .await?;
+
+    // 一直不发请求的客户端不会让 shutdown 永远等下去
+    let _idle = TcpStream::connect(server.local_addr()).await?;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    tokio::time::timeout(Duration::from_secs(2), server.shutdown()).await??;
+
+    Ok(())
+}
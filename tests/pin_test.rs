@@ -1,3 +1,5 @@
+#![allow(unused)]
+
 use std::pin::Pin;
 use tracing_subscriber::util::SubscriberInitExt;
 
@@ -1,3 +1,11 @@
+// 这些测试是学习笔记式的演示代码，保留了故意写出来的冗余写法
+#![allow(
+    unused,
+    clippy::borrow_deref_ref,
+    clippy::unnecessary_operation,
+    clippy::useless_vec
+)]
+
 use std::collections::HashMap;
 use std::fs::File;
 use std::ops::Deref;